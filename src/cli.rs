@@ -2,6 +2,7 @@
 
 use clap::{Parser, Subcommand, ValueEnum};
 use piglog::LogMode;
+use std::path::PathBuf;
 
 #[derive(Parser)]
 #[command(author, version, about, long_about = None)]
@@ -9,6 +10,9 @@ use piglog::LogMode;
 pub struct Cli {
     #[command(subcommand)]
     pub command: Commands,
+    /// Use profiles/<PROFILE>.toml as the root generation file, with its own current/built
+    #[arg(long, global = true, env = "REBOS_PROFILE")]
+    pub profile: Option<String>,
 }
 
 #[derive(Subcommand, Debug)]
@@ -18,6 +22,7 @@ pub enum Commands {
         command: GenCommands,
     },
     Setup,
+    Facts,
     Config {
         #[command(subcommand)]
         command: ConfigCommands,
@@ -62,19 +67,54 @@ pub enum ManagerCommands {
 pub enum ConfigCommands {
     Init,
     Check,
+    Schema {
+        #[arg(value_enum)]
+        format: SchemaFormat,
+    },
+}
+
+#[derive(ValueEnum, Debug, Clone, Copy)]
+pub enum SchemaFormat {
+    Generation,
+    Manager,
+    ManagerOrder,
+    Settings,
 }
 
 #[derive(Subcommand, Debug)]
 pub enum GenCommands {
     Commit(Commit),
     List,
-    Info,
+    Info {
+        /// Show where an item was declared, and through which imports
+        #[clap(long, value_name = "ITEM")]
+        why: Option<String>,
+        /// Show the declaring file next to every item
+        #[clap(long)]
+        sources: bool,
+    },
     Latest,
     
+    /// Compare two generations by number, as shown by 'gen list'
     Diff {
         old: usize,
         new: usize,
     },
+    Plan {
+        #[arg(long, short)]
+        output: Option<PathBuf>,
+    },
+    Apply {
+        plan: PathBuf,
+        #[clap(long)]
+        dry_run: bool,
+        #[clap(long)]
+        resume: bool,
+        #[clap(long)]
+        atomic: bool,
+        #[clap(long, short, default_value_t = 1)]
+        jobs: usize,
+    },
     Current {
         #[command(subcommand)]
         command: CurrentCommands,
@@ -83,9 +123,25 @@ pub enum GenCommands {
 
 #[derive(Subcommand, Debug)]
 pub enum CurrentCommands {
-    Build,
+    Build {
+        #[clap(long)]
+        dry_run: bool,
+        #[clap(long)]
+        resume: bool,
+        #[clap(long)]
+        atomic: bool,
+        #[clap(long, short, default_value_t = 1)]
+        jobs: usize,
+    },
     Rollback(Rollback),
     ToLatest,
+    Verify,
+    Repair {
+        #[clap(long)]
+        remove_others: bool,
+        #[clap(long)]
+        dry_run: bool,
+    },
     Set(SetCurrent),
 }
 
@@ -112,10 +168,15 @@ pub struct Commit {
 
 #[derive(Parser, Debug)]
 pub struct SetCurrent {
+    /// Generation number, as shown by 'gen list'
     pub to: usize,
 }
 
 #[derive(Parser, Debug)]
 pub struct Rollback {
+    /// How many generations to go back from the current one
     pub by: isize,
+    /// Throw away uncommitted changes to the generation file in the state directory
+    #[clap(long)]
+    pub discard: bool,
 }
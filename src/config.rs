@@ -5,9 +5,10 @@ use piglog::prelude::*;
 use piglog::*;
 use std::io;
 
+use crate::cli::SchemaFormat;
 use crate::config;
 use crate::generation;
-use crate::library::ensure_directories_exist;
+use crate::library::{custom_error, ensure_directories_exist};
 
 use crate::places;
 
@@ -18,10 +19,16 @@ const DEFAULT_USER_GEN: &str =
 # --------------------- #
 
 # Import other generation files (relative to ~/.config/rebos/imports/)
+# Subdirectories and globs work (\"desktop/*\"), and \"./name\" is relative to the importing file
 imports = [
     # \"intensive_apps\",
 ]
 
+# Variables usable as ${name} here, in imports, machine files and managers
+# (built in: ${hostname}, ${user}, ${arch}, ${distro}; write $${ for a literal ${)
+[vars]
+# editor = \"neovim\"
+
 # System packages managed by your distro's package manager
 [managers.system]
 items = [
@@ -51,6 +58,7 @@ const DEFAULT_PACKAGE_MANAGER_CONFIG: &str =
 add = \"\"           # Example: \"sudo apt install #:?\"
 remove = \"\"        # Example: \"sudo apt remove #:?\"
 sync = \"\"          # Example: \"sudo apt update\"
+# update = \"\"      # Used when only an item's attributes changed (defaults to 'add')
 upgrade = \"\"       # Example: \"sudo apt upgrade\"
 
 # Display name for this manager (used in output messages)
@@ -62,6 +70,13 @@ hook_name = \"system_packages\"
 [config]
 # Can this manager handle multiple packages at once? (true/false)
 many_args = true
+
+# How each item is written into '#:?' (placeholders: #:name, #:version, #:flags, #:reason)
+# item_format = \"#:name\"
+
+# How an attribute is written when an item sets it ('#:?' is the value)
+# [config.attribute_formats]
+# version = \"=#:?\"
 ";
 
 const DEFAULT_FLATPAK_MANAGER_CONFIG: &str = "# Flatpak application manager
@@ -87,6 +102,16 @@ hook_name = \"crates\"
 many_args = true
 ";
 
+const DEFAULT_SETTINGS: &str =
+"# ------------------- #
+#    Rebos Settings    #
+# ------------------- #
+
+# How generations are stored: \"git\" (default), or \"directory\" for plain numbered
+# snapshot folders (pick before the first commit, switching starts a new history)
+# store = \"git\"
+";
+
 #[derive(PartialEq, Clone, Copy)]
 pub enum ConfigSide {
     User,
@@ -111,6 +136,7 @@ pub fn init_user_config() -> Result<(), io::Error> {
         places::base_user().join("imports"),
         places::base_user().join("hooks"),
         places::base_user().join("managers"),
+        places::base_user().join("profiles"),
     ];
 
     ensure_directories_exist(&directories)?;
@@ -127,6 +153,7 @@ pub fn init_user_config() -> Result<(), io::Error> {
                 .join(&system_hostname)
                 .join("gen.toml"),
         ),
+        (DEFAULT_SETTINGS, places::base_user().join("rebos.toml")),
         (
             DEFAULT_PACKAGE_MANAGER_CONFIG,
             places::base_user().join("managers/system.toml"),
@@ -159,13 +186,14 @@ pub fn init_user_config() -> Result<(), io::Error> {
 pub fn config_for(config: Config, side: ConfigSide) -> Result<PathBuf, std::io::Error> {
     match config {
         Config::Generation => match side {
-            ConfigSide::User => Ok(places::base_user().join("gen.toml")),
-            ConfigSide::System => match generation::current_gen() {
-                Ok(o) => Ok(o),
-                Err(e) => {
-                    error!("Failed to get config path for system generation!");
-                    Err(e)
-                }
+            ConfigSide::User => match places::profile() {
+                Some(p) => Ok(places::profile_gen(p)),
+                None => Ok(places::base_user().join("gen.toml")),
+            },
+            // The system generation is read from the committed revision, see `generation::gen`.
+            ConfigSide::System => {
+                error!("The system generation has no config file, it is read from the current revision!");
+                Err(custom_error("No config file for the system generation!"))
             },
         },
     }
@@ -177,6 +205,9 @@ pub trait ConfigInfoToMessage {
 
 pub enum ConfigError {
     InvalidManager(String, Vec<String>),
+    InvalidManagerFile(String),
+    InvalidGenerationFile(String),
+    InvalidSettingsFile,
     MissingMachine,
     FailedToDeserializeConfigGeneration,
 }
@@ -194,6 +225,15 @@ impl ConfigInfoToMessage for ConfigError {
 
                 message
             }
+            Self::InvalidManagerFile(ref man) => {
+                format!("Failed to read manager file of '{man}'! (See above.)")
+            }
+            Self::InvalidGenerationFile(ref file) => {
+                format!("Failed to read generation file '{file}'! (See above.)")
+            }
+            Self::InvalidSettingsFile => {
+                String::from("Failed to read rebos.toml! (See above.)")
+            }
             Self::MissingMachine => {
                 format!("Missing configuration for machine! (Machine specific gen.toml...)")
             }
@@ -206,6 +246,7 @@ impl ConfigInfoToMessage for ConfigError {
 
 pub enum ConfigWarning {
     UnusedHook(String),
+    WorkingCopyMismatch(String),
 }
 
 impl ConfigInfoToMessage for ConfigWarning {
@@ -214,6 +255,9 @@ impl ConfigInfoToMessage for ConfigWarning {
             Self::UnusedHook(ref hook) => format!(
                 "Hook '{hook}' is never used. (Doesn't match any manager 'hook_name' fields.)"
             ),
+            Self::WorkingCopyMismatch(ref current) => format!(
+                "The generation file in the state directory doesn't match the current {current}. (Builds use the committed {current}.)"
+            ),
         }
     }
 }
@@ -227,10 +271,27 @@ pub fn check_config(
     let mut errors: Vec<ConfigError> = Vec::new();
     let mut warnings: Vec<ConfigWarning> = Vec::new();
 
-    match generation::gen(ConfigSide::User) {
-        Ok(_) => (),
-        Err(_) => errors.push(ConfigError::FailedToDeserializeConfigGeneration),
-    };
+    // Check: Settings.
+    let settings_valid = crate::store::settings().is_ok();
+
+    if !settings_valid {
+        errors.push(ConfigError::InvalidSettingsFile);
+    }
+
+    // Check: Every generation file on its own, so all broken files show up in one run.
+    for path in generation_files()? {
+        if generation::check_file(&path).is_err() {
+            errors.push(ConfigError::InvalidGenerationFile(places::relative_to_user(&path)));
+        }
+    }
+
+    // Merging only makes sense once every file can be read.
+    if errors.is_empty() {
+        match generation::gen(ConfigSide::User) {
+            Ok(_) => (),
+            Err(_) => errors.push(ConfigError::FailedToDeserializeConfigGeneration),
+        };
+    }
 
     let managers = match crate::management::get_managers() {
         Ok(o) => o,
@@ -241,18 +302,15 @@ pub fn check_config(
         }
     };
 
+    // Managers that can't be read are reported, and left out of the checks below.
     let managers_loaded = {
-        let mut ml: Vec<crate::management::Manager> = Vec::new();
+        let mut ml: Vec<(String, crate::management::Manager)> = Vec::new();
 
         for i in managers.iter() {
-            ml.push(match crate::management::load_manager_no_config_check(i) {
-                Ok(o) => o,
-                Err(e) => {
-                    piglog::fatal!("Failed to load manager '{}' due to IO error: {}", i, e);
-
-                    return Err(e);
-                }
-            });
+            match crate::management::load_manager_no_config_check(i) {
+                Ok(o) => ml.push((i.to_string(), o)),
+                Err(_) => errors.push(ConfigError::InvalidManagerFile(i.to_string())),
+            };
         }
 
         ml
@@ -261,19 +319,10 @@ pub fn check_config(
     let hostname = crate::library::hostname()?;
 
     // Check: Manager configuration.
-    for man in managers.iter() {
-        match crate::management::load_manager_no_config_check(man) {
-            Ok(o) => {
-                match o.check_config() {
-                    Ok(_) => (),
-                    Err(e) => errors.push(ConfigError::InvalidManager(man.to_string(), e)),
-                };
-            }
-            Err(e) => {
-                piglog::fatal!("Failed to load manager '{}' due to IO error: {}", man, e);
-
-                return Err(e);
-            }
+    for (man, loaded) in managers_loaded.iter() {
+        match loaded.check_config() {
+            Ok(_) => (),
+            Err(e) => errors.push(ConfigError::InvalidManager(man.to_string(), e)),
         };
     }
 
@@ -288,11 +337,11 @@ pub fn check_config(
 
     // Check: Unused hooks.
     let stages_pre: [&str; 2] = ["pre", "post"];
-    let stages_suf: [&str; 4] = ["add", "remove", "sync", "upgrade"];
+    let stages_suf: [&str; 5] = ["add", "remove", "update", "sync", "upgrade"];
     let mut used_hooks: Vec<String> = Vec::new(); // A list of hook names that would be valid/used.
     used_hooks.push(String::from("pre_build"));
     used_hooks.push(String::from("post_build"));
-    for man in managers_loaded {
+    for (_, man) in managers_loaded {
         for pre in stages_pre {
             for suf in stages_suf {
                 let hook_name = format!("{}_{}_{}", pre, man.hook_name, suf);
@@ -312,6 +361,14 @@ pub fn check_config(
         }
     }
 
+    // Check: The working copy of the generation is the current one. (The store can only be
+    // opened with valid settings.)
+    if settings_valid {
+        if let Some(current) = generation::working_copy_mismatch()? {
+            warnings.push(ConfigWarning::WorkingCopyMismatch(current));
+        }
+    }
+
     let warnings_len = warnings.len();
 
     let misc_info = ConfigCheckMiscInfo { warnings };
@@ -327,6 +384,63 @@ pub fn check_config(
     Ok(Ok(misc_info))
 }
 
+/// Every generation file in the user config: the root, the machine file, imports and profiles.
+fn generation_files() -> Result<Vec<PathBuf>, io::Error> {
+    let mut files: Vec<PathBuf> = vec![
+        config_for(Config::Generation, ConfigSide::User)?,
+        places::base_user()
+            .join("machines")
+            .join(crate::library::hostname()?)
+            .join("gen.toml"),
+    ];
+
+    let mut dirs: Vec<PathBuf> = vec![
+        places::base_user().join("imports"),
+        places::base_user().join("profiles"),
+    ];
+
+    while let Some(dir) = dirs.pop() {
+        let entries = match std::fs::read_dir(&dir) {
+            Ok(o) => o,
+            Err(_) => continue,
+        };
+
+        for entry in entries {
+            let path = entry?.path();
+
+            if path.is_dir() {
+                dirs.push(path);
+            } else if path.extension().map(|x| x == "toml").unwrap_or(false) {
+                files.push(path);
+            }
+        }
+    }
+
+    // With a profile active, the root file is also one of the profiles.
+    Ok(crate::library::remove_array_duplicates(&files)
+        .into_iter()
+        .filter(|x| x.exists())
+        .collect())
+}
+
+/// JSON Schema for one of the config file formats, generated from the types that read them.
+pub fn schema(format: SchemaFormat) -> Result<String, io::Error> {
+    let schema = match format {
+        SchemaFormat::Generation => schemars::schema_for!(generation::Generation),
+        SchemaFormat::Manager => schemars::schema_for!(crate::management::Manager),
+        SchemaFormat::ManagerOrder => schemars::schema_for!(generation::order::ManagerOrder),
+        SchemaFormat::Settings => schemars::schema_for!(crate::store::Settings),
+    };
+
+    match serde_json::to_string_pretty(&schema) {
+        Ok(o) => Ok(o),
+        Err(e) => {
+            error!("Failed to convert schema to JSON: {e}");
+            Err(custom_error("Failed to convert schema to JSON!"))
+        }
+    }
+}
+
 pub fn print_misc_info(misc_info: &ConfigCheckMiscInfo) {
     for w in misc_info.warnings.iter() {
         piglog::warning!("{}", w.msg());
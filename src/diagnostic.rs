@@ -0,0 +1,118 @@
+use colored::Colorize;
+use piglog::prelude::*;
+use piglog::*;
+use std::path::Path;
+
+/// Print a TOML error the way rustc prints its errors: the message, where it is, the offending
+/// line with a caret under it, and a hint if there is one.
+pub fn toml_error(path: &Path, source: &str, e: &toml::de::Error) {
+    let message = e.message().trim();
+
+    error!("{}", message);
+
+    let span = match e.span() {
+        Some(s) => s,
+        None => {
+            generic!("{} {}", "-->".bright_blue().bold(), path.display());
+
+            return;
+        }
+    };
+
+    let (line, column) = position(source, span.start);
+
+    let line_text = source.lines().nth(line - 1).unwrap_or("");
+
+    let gutter = " ".repeat(line.to_string().len());
+    let bar = "|".bright_blue().bold();
+
+    // The caret covers the span, but never runs past the end of the line.
+    let width = span
+        .end
+        .saturating_sub(span.start)
+        .clamp(1, line_text.len().saturating_sub(column - 1).max(1));
+
+    println!("{gutter}{} {}:{line}:{column}", "-->".bright_blue().bold(), path.display());
+    println!("{gutter} {bar}");
+    println!("{} {bar} {}", line.to_string().bright_blue().bold(), line_text);
+    println!(
+        "{gutter} {bar} {}{}",
+        " ".repeat(column - 1),
+        "^".repeat(width).bright_red().bold()
+    );
+
+    if let Some(h) = hint(message) {
+        println!("{gutter} {} {}: {}", "=".bright_blue().bold(), "help".bold(), h);
+    }
+
+    println!();
+}
+
+/// 1-based line and column of a byte offset.
+fn position(source: &str, offset: usize) -> (usize, usize) {
+    let before = &source[..offset.min(source.len())];
+
+    let line = before.matches('\n').count() + 1;
+    let column = before.len() - before.rfind('\n').map(|x| x + 1).unwrap_or(0) + 1;
+
+    (line, column)
+}
+
+fn hint(message: &str) -> Option<String> {
+    if let Some(rest) = message.strip_prefix("unknown field ") {
+        let field = between_backticks(rest).into_iter().next()?;
+        let expected = between_backticks(rest.split_once("expected")?.1);
+
+        return match closest(field, &expected) {
+            Some(s) => Some(format!("did you mean `{s}`?")),
+            None => Some(format!(
+                "valid fields are: {}",
+                expected.iter().map(|x| format!("`{x}`")).collect::<Vec<String>>().join(", ")
+            )),
+        };
+    }
+
+    if let Some(rest) = message.strip_prefix("missing field ") {
+        let field = between_backticks(rest).into_iter().next()?;
+
+        return Some(format!("add `{field} = ...` to this table"));
+    }
+
+    None
+}
+
+fn between_backticks(text: &str) -> Vec<&str> {
+    text.split('`').skip(1).step_by(2).collect()
+}
+
+/// The candidate that is only a typo away from `word`, if any.
+fn closest<'a>(word: &str, candidates: &[&'a str]) -> Option<&'a str> {
+    let limit = (word.len() / 3).max(1);
+
+    candidates
+        .iter()
+        .map(|x| (edit_distance(word, x), *x))
+        .filter(|(d, _)| *d <= limit)
+        .min_by_key(|(d, _)| *d)
+        .map(|(_, x)| x)
+}
+
+fn edit_distance(a: &str, b: &str) -> usize {
+    let b: Vec<char> = b.chars().collect();
+
+    let mut previous: Vec<usize> = (0..=b.len()).collect();
+
+    for (i, ca) in a.chars().enumerate() {
+        let mut current = vec![i + 1];
+
+        for (j, cb) in b.iter().enumerate() {
+            let substitution = previous[j] + usize::from(ca != *cb);
+
+            current.push(substitution.min(previous[j + 1] + 1).min(current[j] + 1));
+        }
+
+        previous = current;
+    }
+
+    previous[b.len()]
+}
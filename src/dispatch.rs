@@ -5,6 +5,7 @@ use crate::management;
 use crate::config;
 use crate::places;
 use crate::library;
+use crate::lock;
 use piglog::prelude::*;
 use piglog::*;
 use colored::Colorize;
@@ -15,6 +16,9 @@ pub fn handle_command(args: cli::Cli) -> Result<(), Box<dyn std::error::Error>>
         cli::Commands::Setup => {
             return handle_setup();
         }
+        cli::Commands::Facts => {
+            return handle_facts();
+        }
         _ => {
             if places::base().exists() == false {
                 error!("It seems that the program is not set up!");
@@ -23,6 +27,22 @@ pub fn handle_command(args: cli::Cli) -> Result<(), Box<dyn std::error::Error>>
         }
     }
 
+    if let Some(profile) = &args.profile {
+        use_profile(profile, &args.command)?;
+    }
+
+    let _lock = match is_mutating(&args.command) {
+        true => {
+            let command: Vec<String> = std::env::args().skip(1).collect();
+
+            match lock::acquire(&command.join(" ")) {
+                Ok(o) => Some(o),
+                Err(_) => return Err("Failed to take the state lock".into()),
+            }
+        }
+        false => None,
+    };
+
     match &args.command {
         Commands::Gen { command } => handle_gen_command(command)?,
         Commands::Config { command } => handle_config_command(command)?,
@@ -38,6 +58,51 @@ pub fn handle_command(args: cli::Cli) -> Result<(), Box<dyn std::error::Error>>
     Ok(())
 }
 
+fn use_profile(name: &str, command: &Commands) -> Result<(), Box<dyn std::error::Error>> {
+    if name.is_empty() || name.contains(['/', '\\']) || name.starts_with('.') {
+        error!("Invalid profile name: '{name}'");
+        return Err("Invalid profile name".into());
+    }
+
+    places::set_profile(name);
+
+    // 'config init' is how the profile file gets created in the first place.
+    let creating = matches!(command, Commands::Config { command: cli::ConfigCommands::Init });
+
+    if !creating && !places::profile_gen(name).exists() {
+        error!("Profile '{name}' does not exist!");
+        note!("Expected file: {}", places::profile_gen(name).display());
+
+        return Err("Profile does not exist".into());
+    }
+
+    Ok(())
+}
+
+/// Whether a command changes the Rebos state or the system, and so has to hold the lock.
+fn is_mutating(command: &Commands) -> bool {
+    match command {
+        Commands::Gen { command } => match command {
+            cli::GenCommands::Commit(_) => true,
+            cli::GenCommands::Apply { dry_run, .. } => !dry_run,
+            cli::GenCommands::Current { command } => match command {
+                cli::CurrentCommands::Build { dry_run, .. } => !dry_run,
+                cli::CurrentCommands::Rollback(_)
+                | cli::CurrentCommands::ToLatest
+                | cli::CurrentCommands::Set(_) => true,
+                cli::CurrentCommands::Verify => false,
+                cli::CurrentCommands::Repair { dry_run, .. } => !dry_run,
+            },
+            _ => false,
+        },
+        Commands::Managers { command, .. } => match command {
+            cli::ManagerCommands::Sync | cli::ManagerCommands::Upgrade { .. } => true,
+            cli::ManagerCommands::ListOthers { remove } => *remove,
+        },
+        _ => false,
+    }
+}
+
 fn handle_setup() -> Result<(), Box<dyn std::error::Error>> {
     info!("Beginning setup...");
 
@@ -48,6 +113,14 @@ fn handle_setup() -> Result<(), Box<dyn std::error::Error>> {
     Ok(())
 }
 
+fn handle_facts() -> Result<(), Box<dyn std::error::Error>> {
+    match crate::facts::Facts::detect() {
+        Ok(o) => o.print(),
+        Err(_) => return Err("Failed to detect host facts".into()),
+    };
+    Ok(())
+}
+
 fn setup() -> Result<(), std::io::Error> {
     match places::setup() {
         Ok(_) => success!("Core directories verified successfully!"),
@@ -63,6 +136,7 @@ fn handle_gen_command(command: &cli::GenCommands) -> Result<(), Box<dyn std::err
             info!("Committing user generation...");
 
             match generation::commit(c.msg.as_str()) {
+                Ok(hash) if hash.is_empty() => (),
                 Ok(_) => success!("Committed generation successfully! (\"{}\")", c.msg),
                 Err(_) => return Err("Failed to commit generation".into()),
             };
@@ -74,13 +148,22 @@ fn handle_gen_command(command: &cli::GenCommands) -> Result<(), Box<dyn std::err
             };
         }
         
-        cli::GenCommands::Info => {
+        cli::GenCommands::Info { why, sources } => {
             let generation = match generation::gen(crate::config::ConfigSide::User) {
                 Ok(o) => o,
                 Err(_) => return Err("Failed to get generation".into()),
             };
 
-            crate::obj_print::generation(&generation);
+            match why {
+                Some(item) => {
+                    if !crate::obj_print::why(&generation, item) {
+                        error!("'{item}' is not declared in any generation file!");
+                        return Err("Item not found".into());
+                    }
+                }
+                None if *sources => crate::obj_print::generation_sources(&generation),
+                None => crate::obj_print::generation(&generation),
+            };
         }
         cli::GenCommands::Latest => {
             match generation::list() {
@@ -136,6 +219,42 @@ fn handle_gen_command(command: &cli::GenCommands) -> Result<(), Box<dyn std::err
 
             library::print_history_gen(&history);
         }
+        cli::GenCommands::Plan { output } => {
+            let plan = match generation::plan() {
+                Ok(o) => o,
+                Err(_) => return Err("Failed to plan build".into()),
+            };
+
+            match output {
+                Some(path) => match plan.save(path) {
+                    Ok(_) => success!("Wrote build plan to: {}", path.display()),
+                    Err(_) => return Err("Failed to write build plan".into()),
+                },
+                None => plan.print(),
+            };
+        }
+        cli::GenCommands::Apply {
+            plan,
+            dry_run,
+            resume,
+            atomic,
+            jobs,
+        } => {
+            info!("Applying build plan: {}", plan.display());
+
+            let options = generation::BuildOptions {
+                dry_run: *dry_run,
+                resume: *resume,
+                atomic: *atomic,
+                jobs: *jobs,
+            };
+
+            match generation::apply(plan, &options) {
+                Ok(_) if *dry_run => success!("Dry run finished, nothing was changed!"),
+                Ok(_) => success!("Applied build plan successfully!"),
+                Err(_) => return Err("Failed to apply build plan".into()),
+            };
+        }
         cli::GenCommands::Current { command } => {
             handle_current_command(command)?;
         }
@@ -146,10 +265,23 @@ fn handle_gen_command(command: &cli::GenCommands) -> Result<(), Box<dyn std::err
 
 fn handle_current_command(command: &cli::CurrentCommands) -> Result<(), Box<dyn std::error::Error>> {
     match command {
-        cli::CurrentCommands::Build => {
+        cli::CurrentCommands::Build {
+            dry_run,
+            resume,
+            atomic,
+            jobs,
+        } => {
             info!("Building 'current' generation...");
 
-            match generation::build() {
+            let options = generation::BuildOptions {
+                dry_run: *dry_run,
+                resume: *resume,
+                atomic: *atomic,
+                jobs: *jobs,
+            };
+
+            match generation::build(&options) {
+                Ok(_) if *dry_run => success!("Dry run finished, nothing was changed!"),
                 Ok(_) => success!("Built generation successfully!"),
                 Err(_) => return Err("Failed to build current generation".into()),
             };
@@ -157,7 +289,7 @@ fn handle_current_command(command: &cli::CurrentCommands) -> Result<(), Box<dyn
         cli::CurrentCommands::Rollback(r) => {
             info!("Rolling back by {} generations...", r.by);
 
-            match generation::rollback(r.by, true) {
+            match generation::rollback(r.by, r.discard, true) {
                 Ok(_) => success!("Rolled back successfully!"),
                 Err(_) => return Err("Failed to rollback generation".into()),
             };
@@ -170,6 +302,35 @@ fn handle_current_command(command: &cli::CurrentCommands) -> Result<(), Box<dyn
                 Err(_) => return Err("Failed to set to latest generation".into()),
             };
         }
+        cli::CurrentCommands::Verify => {
+            info!("Verifying 'built' generation against the system...");
+
+            match generation::verify() {
+                Ok(drift) if drift.unverifiable.is_empty() => {
+                    success!("No drift found, the system matches the built generation!")
+                }
+                Ok(drift) if drift.verified == 0 => {
+                    warning!("Nothing was verified, none of the managers have a 'list' command!")
+                }
+                Ok(drift) => success!(
+                    "No drift found for the {} manager(s) that could be verified!",
+                    drift.verified
+                ),
+                Err(_) => return Err("Failed to verify built generation".into()),
+            };
+        }
+        cli::CurrentCommands::Repair {
+            remove_others,
+            dry_run,
+        } => {
+            info!("Repairing the system to match the 'built' generation...");
+
+            match generation::repair(*remove_others, *dry_run) {
+                Ok(_) if *dry_run => success!("Dry run finished, nothing was changed!"),
+                Ok(_) => success!("Repaired the system successfully!"),
+                Err(_) => return Err("Failed to repair the system".into()),
+            };
+        }
         cli::CurrentCommands::Set(s) => {
             info!("Jumping to generation {}...", s.to);
             
@@ -211,6 +372,13 @@ fn handle_config_command(command: &cli::ConfigCommands) -> Result<(), Box<dyn st
                 }
             };
         }
+        cli::ConfigCommands::Schema { format } => {
+            // Only the schema goes to stdout, so it can be redirected into a file.
+            match config::schema(*format) {
+                Ok(o) => println!("{o}"),
+                Err(_) => return Err("Failed to generate schema".into()),
+            };
+        }
     }
     Ok(())
 }
@@ -0,0 +1,75 @@
+use colored::Colorize;
+use piglog::prelude::*;
+use piglog::*;
+use std::collections::HashMap;
+use std::io;
+use std::path::Path;
+
+use crate::library;
+
+/// What Rebos knows about the host, used to evaluate `when` blocks in generation files.
+#[derive(Debug, Clone)]
+pub struct Facts {
+    pub hostname: String,
+    /// `ID` from os-release. (None if there is no os-release file.)
+    pub os: Option<String>,
+    /// `VERSION_ID` from os-release.
+    pub os_version: Option<String>,
+    pub arch: String,
+}
+
+impl Facts {
+    pub fn detect() -> Result<Self, io::Error> {
+        let os_release = os_release();
+
+        Ok(Self {
+            hostname: library::hostname()?,
+            os: os_release.get("ID").cloned(),
+            os_version: os_release.get("VERSION_ID").cloned(),
+            arch: std::env::consts::ARCH.to_string(),
+        })
+    }
+
+    pub fn print(&self) {
+        let unknown = String::from("unknown");
+
+        for (name, value) in [
+            ("hostname", &self.hostname),
+            ("os", self.os.as_ref().unwrap_or(&unknown)),
+            ("os_version", self.os_version.as_ref().unwrap_or(&unknown)),
+            ("arch", &self.arch),
+        ] {
+            info!("{}: {}", name, value.bright_cyan());
+        }
+    }
+}
+
+/// Whether an executable called `name` can be found in `PATH`.
+pub fn has_binary(name: &str) -> bool {
+    let path = match std::env::var_os("PATH") {
+        Some(s) => s,
+        None => return false,
+    };
+
+    std::env::split_paths(&path).any(|x| x.join(name).is_file())
+}
+
+fn os_release() -> HashMap<String, String> {
+    for path in ["/etc/os-release", "/usr/lib/os-release"] {
+        if let Ok(o) = std::fs::read_to_string(Path::new(path)) {
+            return parse_os_release(&o);
+        }
+    }
+
+    HashMap::new()
+}
+
+fn parse_os_release(content: &str) -> HashMap<String, String> {
+    content
+        .lines()
+        .map(|x| x.trim())
+        .filter(|x| !x.is_empty() && !x.starts_with('#'))
+        .filter_map(|x| x.split_once('='))
+        .map(|(k, v)| (k.to_string(), v.trim_matches(|c| c == '"' || c == '\'').to_string()))
+        .collect()
+}
@@ -0,0 +1,94 @@
+use colored::Colorize;
+use piglog::prelude::*;
+use piglog::*;
+use std::io;
+
+use super::order::get_order;
+use super::{Generation, Item};
+use crate::management::load_manager;
+
+#[derive(Debug)]
+pub struct ManagerDrift {
+    pub manager: String,
+    /// Declared in the generation, but not installed.
+    pub missing: Vec<Item>,
+    /// Installed, but not declared in the generation.
+    pub others: Vec<String>,
+}
+
+/// Differences between a generation and what the managers report as installed.
+#[derive(Debug, Default)]
+pub struct Drift {
+    pub managers: Vec<ManagerDrift>,
+    /// Managers without a `list` command, which can't be checked.
+    pub unverifiable: Vec<String>,
+    /// How many managers were checked.
+    pub verified: usize,
+}
+
+impl Drift {
+    pub fn detect(gen: &Generation) -> Result<Self, io::Error> {
+        let mut drift = Self::default();
+
+        for i in get_order(gen)?.iter() {
+            let man = load_manager(i)?;
+
+            if man.list.is_none() {
+                drift.unverifiable.push(i.to_string());
+
+                continue;
+            }
+
+            drift.verified += 1;
+
+            let items = &gen.managers.get(i).unwrap().items;
+
+            let installed = man.list()?;
+
+            let missing: Vec<Item> = items
+                .iter()
+                .filter(|x| !x.name.trim().is_empty() && !installed.contains(&x.name))
+                .cloned()
+                .collect();
+
+            let others: Vec<String> = installed
+                .into_iter()
+                .filter(|x| !items.iter().any(|i| i.name == *x))
+                .collect();
+
+            if !missing.is_empty() || !others.is_empty() {
+                drift.managers.push(ManagerDrift {
+                    manager: i.to_string(),
+                    missing,
+                    others,
+                });
+            }
+        }
+
+        Ok(drift)
+    }
+
+    pub fn missing_count(&self) -> usize {
+        self.managers.iter().map(|x| x.missing.len()).sum()
+    }
+
+    pub fn others_count(&self) -> usize {
+        self.managers.iter().map(|x| x.others.len()).sum()
+    }
+
+    pub fn print(&self) {
+        for m in self.managers.iter().filter(|x| !x.missing.is_empty()) {
+            info!("{}: ({} missing)", m.manager, m.missing.len());
+
+            for i in m.missing.iter() {
+                println!("{}", format!("- {}", i).bright_red().bold());
+            }
+
+            println!();
+        }
+
+        for i in self.unverifiable.iter() {
+            warning!("Manager '{}' has no 'list' command, so it can't be verified!", i);
+        }
+    }
+}
@@ -0,0 +1,122 @@
+use piglog::prelude::*;
+use piglog::*;
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::io;
+
+use super::provenance::Provenance;
+use super::Generation;
+use crate::library::*;
+use crate::management::load_manager;
+
+/// Drop every item that a manager already has, warning about each one.
+pub fn dedup(gen: &mut Generation) {
+    let mut managers: Vec<String> = gen.managers.keys().cloned().collect();
+    managers.sort();
+
+    for m in managers.iter() {
+        let items = &mut gen.managers.get_mut(m).unwrap().items;
+
+        let sources = sources(&gen.provenance, m);
+
+        let mut seen: HashMap<String, usize> = HashMap::new();
+        let mut kept = Vec::new();
+
+        for item in std::mem::take(items).into_iter() {
+            let occurrence = seen.entry(item.name.to_string()).or_default();
+
+            *occurrence += 1;
+
+            if *occurrence == 1 {
+                kept.push(item);
+
+                continue;
+            }
+
+            let files = sources.get(item.name.as_str());
+            let first = files.and_then(|x| x.first()).copied().unwrap_or("unknown");
+            let this = files.and_then(|x| x.get(*occurrence - 1)).copied().unwrap_or("unknown");
+
+            warning!("Duplicate item in '{m}': '{}' (declared in {first} and {this})", item.name);
+
+            let original = kept.iter().find(|x| x.name == item.name).unwrap();
+
+            if *original != item {
+                note!("The declarations differ, keeping the first one: {}", original);
+            }
+        }
+
+        *items = kept;
+    }
+}
+
+/// Fail if an item is declared in two managers that are marked as mutually exclusive. (Marking
+/// it in either manager's `conflicts` is enough.)
+pub fn check_conflicts(gen: &Generation) -> Result<(), io::Error> {
+    let mut pairs: BTreeSet<(String, String)> = BTreeSet::new();
+
+    for m in gen.managers.keys() {
+        for other in load_manager(m)?.conflicts.into_iter() {
+            if !gen.managers.contains_key(&other) || other == *m {
+                continue;
+            }
+
+            pairs.insert(match other < *m {
+                true => (other, m.to_string()),
+                false => (m.to_string(), other),
+            });
+        }
+    }
+
+    let mut conflicts: Vec<String> = Vec::new();
+
+    for (a, b) in pairs.iter() {
+        let sources_a = sources(&gen.provenance, a);
+        let sources_b = sources(&gen.provenance, b);
+
+        let items_b = &gen.managers.get(b).unwrap().items;
+
+        for item in gen.managers.get(a).unwrap().items.iter() {
+            if !items_b.iter().any(|x| x.name == item.name) {
+                continue;
+            }
+
+            let file = |s: &BTreeMap<&str, Vec<&str>>| {
+                s.get(item.name.as_str())
+                    .and_then(|x| x.first())
+                    .copied()
+                    .unwrap_or("unknown")
+                    .to_string()
+            };
+
+            conflicts.push(format!(
+                "'{}' is in both '{a}' ({}) and '{b}' ({}), which conflict!",
+                item.name,
+                file(&sources_a),
+                file(&sources_b)
+            ));
+        }
+    }
+
+    if !conflicts.is_empty() {
+        for c in conflicts.iter() {
+            error!("{}", c);
+        }
+
+        note!("Remove the item from one of the managers, or 'exclude' it there.");
+
+        return Err(custom_error("Items in conflicting managers!"));
+    }
+
+    Ok(())
+}
+
+/// The declaring files of each item of a manager, in the order the declarations were merged.
+fn sources<'a>(provenance: &'a [Provenance], manager: &str) -> BTreeMap<&'a str, Vec<&'a str>> {
+    let mut sources: BTreeMap<&str, Vec<&str>> = BTreeMap::new();
+
+    for p in provenance.iter().filter(|x| x.manager == manager) {
+        sources.entry(p.item.as_str()).or_default().push(p.file());
+    }
+
+    sources
+}
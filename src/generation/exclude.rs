@@ -0,0 +1,62 @@
+use std::path::Path;
+
+use super::{Generation, Item};
+use crate::library::*;
+use crate::places;
+
+/// One `exclude` pattern, and what it removed from the merged generation.
+#[derive(PartialEq, Debug, Clone)]
+pub struct Exclusion {
+    pub manager: String,
+    /// Item name, `*` and `?` work.
+    pub pattern: String,
+    /// The file that declared the pattern. (Relative to the user config, if it is in there.)
+    pub source: String,
+    pub excluded: Vec<Item>,
+}
+
+/// Move the `exclude` lists out of a freshly read file, remembering where they came from.
+pub fn collect(gen: &mut Generation, path: &Path) {
+    let source = places::relative_to_user(path);
+
+    let mut managers: Vec<&String> = gen.managers.keys().collect();
+    managers.sort();
+
+    let mut exclusions: Vec<Exclusion> = Vec::new();
+
+    for m in managers.into_iter() {
+        for pattern in gen.managers.get(m).unwrap().exclude.iter() {
+            exclusions.push(Exclusion {
+                manager: m.to_string(),
+                pattern: pattern.to_string(),
+                source: source.to_string(),
+                excluded: Vec::new(),
+            });
+        }
+    }
+
+    for items in gen.managers.values_mut() {
+        items.exclude.clear();
+    }
+
+    gen.exclusions.extend(exclusions);
+}
+
+/// Remove every item matched by an exclusion. Only done once everything has been merged, so a
+/// file can exclude items no matter which file declares them.
+pub fn apply(gen: &mut Generation) {
+    for e in gen.exclusions.iter_mut() {
+        let items = match gen.managers.get_mut(&e.manager) {
+            Some(s) => &mut s.items,
+            None => continue,
+        };
+
+        items.retain(|x| match glob_match(&e.pattern, &x.name) {
+            true => {
+                e.excluded.push(x.clone());
+                false
+            }
+            false => true,
+        });
+    }
+}
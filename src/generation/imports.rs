@@ -0,0 +1,166 @@
+use piglog::prelude::*;
+use piglog::*;
+use std::collections::HashSet;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use super::{provenance, read_to_gen, Generation, GenerationUtils};
+use crate::facts::Facts;
+use crate::library::*;
+use crate::places;
+use crate::vars::Vars;
+
+/// Reads imports depth-first. Every file is read at most once, so diamonds are fine, and
+/// importing a file that is still being imported is reported as a cycle.
+pub struct Resolver<'a> {
+    facts: &'a Facts,
+    vars: Option<&'a Vars>,
+    /// The files currently being imported, outermost first.
+    chain: Vec<PathBuf>,
+    visited: HashSet<PathBuf>,
+}
+
+impl<'a> Resolver<'a> {
+    pub fn new(facts: &'a Facts, vars: Option<&'a Vars>) -> Self {
+        Self {
+            facts,
+            vars,
+            chain: Vec::new(),
+            visited: HashSet::new(),
+        }
+    }
+
+    /// Read the file at `path`, with everything it imports merged in.
+    pub fn read(&mut self, path: &Path) -> Result<Generation, io::Error> {
+        let path = canonical(path);
+
+        self.visited.insert(path.clone());
+
+        let mut gen = read_to_gen(&path, self.facts, self.vars)?;
+
+        self.chain.push(path.clone());
+
+        provenance::record(&mut gen, &self.chain);
+
+        for i in std::mem::take(&mut gen.imports).iter() {
+            for i_path in find(i, &path)? {
+                if self.chain.contains(&i_path) {
+                    // The whole chain from the top, so it is clear how the cycle was reached.
+                    let cycle: Vec<String> = self
+                        .chain
+                        .iter()
+                        .chain(std::iter::once(&i_path))
+                        .map(|x| places::relative_to_user(x))
+                        .collect();
+
+                    error!("Import cycle: {}", cycle.join(" -> "));
+
+                    return Err(custom_error("Import cycle!"));
+                }
+
+                if self.visited.contains(&i_path) {
+                    continue;
+                }
+
+                let i_gen = self.read(&i_path)?;
+
+                gen.extend(i_gen);
+            }
+        }
+
+        self.chain.pop();
+
+        Ok(gen)
+    }
+}
+
+/// The files an entry of `imports` refers to.
+///
+/// Entries are relative to the imports directory, or to the importing file if they start with
+/// `./` or `../`. Entries with `*` or `?` are matched against the files and directories there.
+fn find(import: &str, from: &Path) -> Result<Vec<PathBuf>, io::Error> {
+    let base = match import.starts_with("./") || import.starts_with("../") {
+        true => from.parent().map(|x| x.to_path_buf()).unwrap_or_default(),
+        false => places::base_user().join("imports"),
+    };
+
+    if !import.contains(['*', '?']) {
+        let path = base.join(format!("{import}.toml"));
+
+        if !path.is_file() {
+            error!("Import '{import}' not found! (Imported by {})", places::relative_to_user(from));
+            note!("Expected file: {}", path.display());
+
+            return Err(custom_error("Import not found!"));
+        }
+
+        return Ok(vec![canonical(&path)]);
+    }
+
+    let segments: Vec<&str> = import.split('/').collect();
+
+    let mut found: Vec<PathBuf> = vec![base];
+
+    for (i, segment) in segments.iter().enumerate() {
+        let last = i == segments.len() - 1;
+
+        let mut next: Vec<PathBuf> = Vec::new();
+
+        for dir in found.iter() {
+            if !segment.contains(['*', '?']) {
+                next.push(match last {
+                    true => dir.join(format!("{segment}.toml")),
+                    false => dir.join(segment),
+                });
+
+                continue;
+            }
+
+            let entries = match std::fs::read_dir(dir) {
+                Ok(o) => o,
+                Err(_) => continue,
+            };
+
+            for entry in entries.filter_map(|x| x.ok()) {
+                let entry_path = entry.path();
+
+                let name = match last {
+                    true if entry_path.extension().map(|x| x == "toml").unwrap_or(false) => {
+                        entry_path.file_stem()
+                    }
+                    true => None,
+                    false if entry_path.is_dir() => entry_path.file_name(),
+                    false => None,
+                };
+
+                if name.and_then(|x| x.to_str()).map(|x| glob_match(segment, x)).unwrap_or(false) {
+                    next.push(entry_path);
+                }
+            }
+        }
+
+        found = next;
+    }
+
+    let mut found: Vec<PathBuf> = found
+        .into_iter()
+        .filter(|x| x.is_file())
+        .map(|x| canonical(&x))
+        .collect();
+
+    // Directory listings come in any order, but the merged generation should not.
+    found.sort();
+
+    if found.is_empty() {
+        warning!(
+            "Import '{import}' matched no files! (Imported by {})",
+            places::relative_to_user(from)
+        );
+    }
+
+    Ok(found)
+}
+
+fn canonical(path: &Path) -> PathBuf {
+    std::fs::canonicalize(path).unwrap_or(path.to_path_buf())
+}
@@ -0,0 +1,138 @@
+use schemars::gen::SchemaGenerator;
+use schemars::schema::Schema;
+use schemars::JsonSchema;
+use serde::de::{self, Deserializer, MapAccess, Visitor};
+use serde::{Deserialize, Serialize};
+use std::fmt;
+
+/// A single entry of a manager's `items`.
+///
+/// Written either as a plain string (`"git"`), or as a table with attributes
+/// (`{ name = "neovim", version = "0.10.*", flags = "--locked", reason = "editor" }`).
+/// Items are identified by their name, the attributes can change without the item being removed.
+#[derive(PartialEq, Eq, Hash, Serialize, Debug, Clone, Default)]
+#[serde(into = "ItemDef")]
+pub struct Item {
+    pub name: String,
+    pub version: Option<String>,
+    pub flags: Option<String>,
+    pub reason: Option<String>,
+}
+
+/// Attributes that managers can use as `#:<attribute>` placeholders.
+pub const ATTRIBUTES: [&str; 3] = ["version", "flags", "reason"];
+
+#[derive(Serialize, JsonSchema, Clone)]
+#[serde(untagged)]
+enum ItemDef {
+    Name(String),
+    Detailed(ItemDetails),
+}
+
+#[derive(Serialize, Deserialize, JsonSchema, Clone)]
+#[serde(deny_unknown_fields)]
+struct ItemDetails {
+    name: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    version: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    flags: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    reason: Option<String>,
+}
+
+impl JsonSchema for Item {
+    fn schema_name() -> String {
+        String::from("Item")
+    }
+
+    fn json_schema(gen: &mut SchemaGenerator) -> Schema {
+        ItemDef::json_schema(gen)
+    }
+}
+
+// Not `#[serde(untagged)]`, that would hide why a table was rejected behind a generic error.
+impl<'de> Deserialize<'de> for Item {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        deserializer.deserialize_any(ItemVisitor)
+    }
+}
+
+struct ItemVisitor;
+
+impl<'de> Visitor<'de> for ItemVisitor {
+    type Value = Item;
+
+    fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "an item name, or a table with a `name`")
+    }
+
+    fn visit_str<E: de::Error>(self, name: &str) -> Result<Item, E> {
+        Ok(Item::new(name))
+    }
+
+    fn visit_map<A: MapAccess<'de>>(self, map: A) -> Result<Item, A::Error> {
+        let d = ItemDetails::deserialize(de::value::MapAccessDeserializer::new(map))?;
+
+        Ok(Item {
+            name: d.name,
+            version: d.version,
+            flags: d.flags,
+            reason: d.reason,
+        })
+    }
+}
+
+impl From<Item> for ItemDef {
+    fn from(item: Item) -> Self {
+        if !item.has_attributes() {
+            return Self::Name(item.name);
+        }
+
+        Self::Detailed(ItemDetails {
+            name: item.name,
+            version: item.version,
+            flags: item.flags,
+            reason: item.reason,
+        })
+    }
+}
+
+impl Item {
+    pub fn new(name: &str) -> Self {
+        Self {
+            name: name.to_string(),
+            ..Default::default()
+        }
+    }
+
+    pub fn has_attributes(&self) -> bool {
+        ATTRIBUTES.iter().any(|x| self.attribute(x).is_some())
+    }
+
+    pub fn attribute(&self, attribute: &str) -> Option<&str> {
+        match attribute {
+            "version" => self.version.as_deref(),
+            "flags" => self.flags.as_deref(),
+            "reason" => self.reason.as_deref(),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for Item {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.name)?;
+
+        let attributes: Vec<String> = ATTRIBUTES
+            .iter()
+            .filter_map(|x| self.attribute(x).map(|v| format!("{x} = {v}")))
+            .collect();
+
+        if !attributes.is_empty() {
+            write!(f, " ({})", attributes.join(", "))?;
+        }
+
+        Ok(())
+    }
+}
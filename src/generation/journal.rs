@@ -0,0 +1,143 @@
+use piglog::prelude::*;
+use piglog::*;
+use serde::{Deserialize, Serialize};
+use std::io;
+use std::path::PathBuf;
+
+use super::Item;
+use crate::library::*;
+use crate::management::Operation;
+use crate::places;
+
+#[derive(PartialEq, Serialize, Deserialize, Debug, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct JournalEntry {
+    pub manager: String,
+    pub phase: Operation,
+    pub batch: usize,
+    #[serde(default)]
+    pub items: Vec<Item>,
+    /// What updated items looked like before, so the update can be reverted.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub previous: Vec<Item>,
+}
+
+/// Progress of a build, so a failed build can be picked up where it stopped.
+#[derive(PartialEq, Serialize, Deserialize, Debug, Default)]
+#[serde(deny_unknown_fields, default)]
+pub struct Journal {
+    /// The hash that was built when the build started. (None if it is the first build.)
+    pub from: Option<String>,
+    /// The hash being built.
+    pub to: String,
+    pub done: Vec<JournalEntry>,
+    #[serde(skip)]
+    persist: bool,
+}
+
+pub fn path() -> PathBuf {
+    places::gens().join("journal.toml")
+}
+
+impl Journal {
+    pub fn new(from: Option<String>, to: &str) -> Self {
+        Self {
+            from,
+            to: to.to_string(),
+            done: Vec::new(),
+            persist: false,
+        }
+    }
+
+    pub fn load() -> Result<Option<Self>, io::Error> {
+        let journal_string = match std::fs::read_to_string(path()) {
+            Ok(o) => o,
+            Err(e) => {
+                if e.kind() == io::ErrorKind::NotFound {
+                    return Ok(None);
+                }
+
+                error!("Failed to read build journal!");
+
+                return Err(e);
+            }
+        };
+
+        match toml::from_str(&journal_string) {
+            Ok(o) => Ok(Some(o)),
+            Err(e) => {
+                error!("Failed to deserialize build journal:");
+                error!("{e:#?}");
+
+                Err(custom_error("Failed to deserialize build journal!"))
+            }
+        }
+    }
+
+    /// Only a persisted journal is written to disk. (Dry runs should leave it alone.)
+    pub fn set_persist(&mut self, persist: bool) {
+        self.persist = persist;
+    }
+
+    pub fn is_done(&self, manager: &str, phase: Operation, batch: usize) -> bool {
+        self.done
+            .iter()
+            .any(|x| x.manager == manager && x.phase == phase && x.batch == batch)
+    }
+
+    pub fn record(
+        &mut self,
+        manager: &str,
+        phase: Operation,
+        batch: usize,
+        items: &[Item],
+        previous: &[Item],
+    ) -> Result<(), io::Error> {
+        self.done.push(JournalEntry {
+            manager: manager.to_string(),
+            phase,
+            batch,
+            items: items.to_vec(),
+            previous: previous.to_vec(),
+        });
+
+        self.save()
+    }
+
+    pub fn save(&self) -> Result<(), io::Error> {
+        if !self.persist {
+            return Ok(());
+        }
+
+        let journal_string = match toml::to_string(self) {
+            Ok(o) => o,
+            Err(_e) => {
+                error!("Failed to convert build journal to string!");
+                return Err(custom_error("Failed to convert build journal to string!"));
+            }
+        };
+
+        match std::fs::write(path(), journal_string) {
+            Ok(_) => Ok(()),
+            Err(e) => {
+                error!("Failed to write build journal!");
+                Err(e)
+            }
+        }
+    }
+
+    /// Delete the journal file, once the build it tracks has finished.
+    pub fn finish(&self) -> Result<(), io::Error> {
+        if !self.persist || !path().exists() {
+            return Ok(());
+        }
+
+        match std::fs::remove_file(path()) {
+            Ok(_) => Ok(()),
+            Err(e) => {
+                error!("Failed to remove build journal!");
+                Err(e)
+            }
+        }
+    }
+}
@@ -1,60 +1,88 @@
 #![allow(dead_code)]
 
 use colored::Colorize;
-use std::path::{Path, PathBuf};
-use std::collections::HashMap;
+use std::path::Path;
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::sync::{mpsc, Mutex};
+use std::thread;
 use piglog::prelude::*;
 use piglog::*;
+use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
 use std::io;
+use std::time::{Duration, Instant};
 
 use crate::config::config_for;
 use crate::config::{Config, ConfigSide};
-use crate::git;
+use crate::diagnostic;
+use crate::facts::Facts;
+use crate::store;
 use crate::hook;
 use crate::library::*;
+use crate::output::{self, log_core_print, log_generic_print};
 
-use crate::management::load_manager;
+use crate::management::{load_manager, Manager, Operation};
+use crate::obj_print_boilerplate::macros::print_entry;
 use crate::places;
+use crate::vars::{self, Vars};
 
+pub mod drift;
+pub mod duplicates;
+pub mod exclude;
+pub mod imports;
+pub mod item;
+pub mod journal;
+pub mod numbers;
+pub mod order;
+pub mod plan;
+pub mod provenance;
+pub mod summary;
+pub mod when;
 
+pub use item::Item;
 
+use drift::Drift;
+use journal::{Journal, JournalEntry};
+use plan::{BuildPlan, PlanStep};
+use summary::BuildSummary;
 
-#[derive(PartialEq, Serialize, Deserialize, Debug)]
-#[serde(deny_unknown_fields, default)]
-pub struct ManagerOrder {
-    pub begin: Vec<String>,
-    pub end: Vec<String>,
-}
 
-impl Default for ManagerOrder {
-    fn default() -> Self {
-        Self {
-            begin: Vec::new(),
-            end: Vec::new(),
-        }
-    }
-}
 
-#[derive(PartialEq, Serialize, Deserialize, Debug)]
+#[derive(PartialEq, Serialize, Deserialize, JsonSchema, Debug)]
 #[serde(deny_unknown_fields, default)]
 pub struct Items {
-    pub items: Vec<String>,
+    pub items: Vec<Item>,
+    /// Names (or patterns) to drop from the merged generation, even if another file declares them.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub exclude: Vec<String>,
 }
 
 impl Default for Items {
     fn default() -> Self {
-        Self { items: Vec::new() }
+        Self {
+            items: Vec::new(),
+            exclude: Vec::new(),
+        }
     }
 }
 
 
 
-#[derive(PartialEq, Serialize, Deserialize, Debug)]
+#[derive(PartialEq, Serialize, Deserialize, JsonSchema, Debug)]
 #[serde(deny_unknown_fields, default)]
 pub struct Generation {
     pub imports: Vec<String>,
     pub managers: HashMap<String, Items>,
+    /// Conditional blocks, resolved against the host facts when the files are read.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub when: Vec<when::When>,
+    /// Variables for `${name}`. (Already substituted by the time the file is deserialized.)
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    pub vars: BTreeMap<String, String>,
+    #[serde(skip)]
+    pub exclusions: Vec<exclude::Exclusion>,
+    #[serde(skip)]
+    pub provenance: Vec<provenance::Provenance>,
 }
 
 impl Default for Generation {
@@ -62,6 +90,25 @@ impl Default for Generation {
         Generation {
             imports: Vec::new(),
             managers: HashMap::new(),
+            when: Vec::new(),
+            vars: BTreeMap::new(),
+            exclusions: Vec::new(),
+            provenance: Vec::new(),
+        }
+    }
+}
+
+impl Generation {
+    /// Merge in the `when` blocks that match the host, and drop the rest.
+    pub fn resolve_when(&mut self, facts: &Facts) {
+        for w in std::mem::take(&mut self.when).into_iter() {
+            if w.matches(facts) {
+                self.extend(Generation {
+                    imports: w.imports,
+                    managers: w.managers,
+                    ..Default::default()
+                });
+            }
         }
     }
 }
@@ -69,20 +116,25 @@ impl Default for Generation {
 impl GenerationUtils for Generation {
     fn extend(&mut self, other_gen: Generation) {
         self.imports.extend(other_gen.imports);
+        self.when.extend(other_gen.when);
+        self.exclusions.extend(other_gen.exclusions);
+        self.provenance.extend(other_gen.provenance);
 
         for i in other_gen.managers.keys() {
+            let other_items = other_gen.managers.get(i).unwrap();
+
             match self.managers.get_mut(i) {
-                Some(s) => s
-                    .items
-                    .extend(other_gen.managers.get(i).unwrap().items.clone()),
+                Some(s) => {
+                    s.items.extend(other_items.items.clone());
+                    s.exclude.extend(other_items.exclude.clone());
+                }
                 None => {
-                    self.managers
-                        .insert(i.to_string(), Items { items: Vec::new() });
-                    self.managers
-                        .get_mut(i)
-                        .unwrap()
-                        .items
-                        .extend(other_gen.managers.get(i).unwrap().items.clone());
+                    self.managers.insert(i.to_string(), Items::default());
+
+                    let s = self.managers.get_mut(i).unwrap();
+
+                    s.items.extend(other_items.items.clone());
+                    s.exclude.extend(other_items.exclude.clone());
                 }
             };
         }
@@ -94,60 +146,48 @@ pub trait GenerationUtils {
 }
 
 pub fn gen(side: ConfigSide) -> Result<Generation, io::Error> {
-    let mut generation = match read_to_gen(&config_for(Config::Generation, side)?) {
-        Ok(o) => o,
-        Err(e) => return Err(e),
-    };
-
-    let system_hostname = match crate::library::hostname() {
-        Ok(o) => o,
-        Err(e) => return Err(e),
-    };
+    // The system generation is the revision named in 'current', whatever the working copy says.
+    // (It was merged, interpolated and filtered when it was committed.)
+    if side == ConfigSide::System {
+        let current_hash = match get_current_hash() {
+            Ok(o) => o,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => {
+                error!("No current generation found!");
+                return Err(custom_error("No current generation found!"));
+            }
+            Err(e) => return Err(e),
+        };
 
-    if side == ConfigSide::User {
-        generation.extend(read_to_gen(
-            &places::base_user()
-                .join("machines")
-                .join(&system_hostname)
-                .join("gen.toml"),
-        )?);
+        return get_gen_from_hash(&current_hash);
     }
 
-    while generation.imports.len() > 0 {
-        let gen_imports = std::mem::take(&mut generation.imports);
+    let facts = Facts::detect()?;
 
-        for i in gen_imports.iter() {
-            let i_gen = read_to_gen(
-                &places::base_user()
-                    .join("imports")
-                    .join(format!("{i}.toml")),
-            )?;
+    let vars = vars::global(&facts)?;
 
-            generation.extend(i_gen);
-        }
+    let mut resolver = imports::Resolver::new(&facts, Some(&vars));
 
-        let after_gen_imports = std::mem::take(&mut generation.imports);
+    let mut generation = match resolver.read(&config_for(Config::Generation, side)?) {
+        Ok(o) => o,
+        Err(e) => return Err(e),
+    };
 
-        for i in 0..after_gen_imports.len() {
-            if gen_imports.contains(&after_gen_imports[i]) {
-                generation.imports[i] = String::new();
-            }
-        }
+    generation.extend(resolver.read(
+        &places::base_user()
+            .join("machines")
+            .join(&facts.hostname)
+            .join("gen.toml"),
+    )?);
 
-        generation.imports = generation
-            .imports
-            .into_iter()
-            .filter(|x| *x != String::new())
-            .collect();
-    }
+    exclude::apply(&mut generation);
 
     Ok(generation)
 }
 
 
 
-fn read_to_gen(path: &Path) -> Result<Generation, io::Error> {
-    let gen_string = match std::fs::read_to_string(path) {
+fn read_to_gen(path: &Path, facts: &Facts, vars: Option<&Vars>) -> Result<Generation, io::Error> {
+    let mut gen_string = match std::fs::read_to_string(path) {
         Ok(o) => o,
         Err(e) => {
             if e.kind() == io::ErrorKind::NotFound {
@@ -158,22 +198,40 @@ fn read_to_gen(path: &Path) -> Result<Generation, io::Error> {
         }
     };
 
-    match toml::from_str(&gen_string) {
-        Ok(o) => Ok(o),
+    if let Some(vars) = vars {
+        gen_string = vars::interpolate(path, &gen_string, vars)?;
+    }
+
+    match toml::from_str::<Generation>(&gen_string) {
+        Ok(mut o) => {
+            // Only needed for substitution, which is done by now.
+            o.vars.clear();
+            o.resolve_when(facts);
+            exclude::collect(&mut o, path);
+
+            Ok(o)
+        }
         Err(e) => {
-            error!("Failed to deserialize generation file:");
-            error!("{e:#?}");
-            error!("Path: '{}'", path.display());
+            diagnostic::toml_error(path, &gen_string, &e);
 
             Err(custom_error("Failed to deserialize generation!"))
         }
     }
 }
 
+/// Read a single generation file, without following its imports. (Reports whatever is wrong.)
+pub fn check_file(path: &Path) -> Result<(), io::Error> {
+    let facts = Facts::detect()?;
+
+    read_to_gen(path, &facts, Some(&vars::global(&facts)?))?;
+
+    Ok(())
+}
+
 pub fn get_gen_from_hash(hash: &str) -> Result<Generation, io::Error> {
-    let repo = git::repo();
+    let repo = store::store()?;
     
-    match repo.get_file_content_at_hash(hash, "gen.toml") {
+    match repo.file_at(hash, &gen_file_in_repo()) {
         Ok(content) => {
             match toml::from_str(&content) {
                 Ok(gen) => Ok(gen),
@@ -192,9 +250,25 @@ pub fn get_gen_from_hash(hash: &str) -> Result<Generation, io::Error> {
     }
 }
 
+/// Where the generation file of the active profile is, relative to the state repo.
+fn gen_file_in_repo() -> String {
+    places::gens()
+        .strip_prefix(places::base())
+        .unwrap_or(&places::gens())
+        .join("gen.toml")
+        .display()
+        .to_string()
+}
+
 pub fn get_current_hash() -> Result<String, io::Error> {
-    let repo = git::repo();
-    repo.get_current_hash()
+    match std::fs::read_to_string(places::gens().join("current")) {
+        Ok(content) => Ok(content.trim().to_string()),
+        // States from before 'current' was written only have the checked out commit to go by.
+        Err(e) if e.kind() == io::ErrorKind::NotFound && places::profile().is_none() => {
+            store::store()?.current_revision()
+        }
+        Err(e) => Err(e),
+    }
 }
 
 pub fn get_built_hash() -> Result<String, io::Error> {
@@ -214,11 +288,14 @@ pub fn get_built_hash() -> Result<String, io::Error> {
 pub fn commit(msg: &str) -> Result<String, io::Error> {
     
 
-    let user_gen = match gen(ConfigSide::User) {
+    let mut user_gen = match gen(ConfigSide::User) {
         Ok(o) => o,
         Err(e) => return Err(e),
     };
 
+    duplicates::dedup(&mut user_gen);
+    duplicates::check_conflicts(&user_gen)?;
+
     let user_gen_string = match toml::to_string(&user_gen) {
         Ok(o) => o,
         Err(_e) => {
@@ -227,8 +304,32 @@ pub fn commit(msg: &str) -> Result<String, io::Error> {
         }
     };
 
+    commit_content(&user_gen_string, msg)
+}
+
+/// Write the generation file of the active profile, and commit it as a new generation.
+fn commit_content(content: &str, msg: &str) -> Result<String, io::Error> {
+    // Only a changed generation file makes a new generation, nothing else gets a number.
+    if let Some(latest) = numbers::generations()?.first() {
+        let committed = store::store()?.file_at(&latest.hash, &gen_file_in_repo())?;
+
+        if same_generation(&committed, content) {
+            warning!("No changes to commit");
+
+            // The config is the latest generation, so that is what the next build should be.
+            if get_current_hash().ok().as_deref() != Some(latest.hash.as_str()) {
+                info!("Generation {} already matches the config, making it current...", latest.number);
+                set_current_hash(&latest.hash, true)?;
+            }
+
+            return Ok(String::new());
+        }
+    }
+
+    ensure_directories_exist(&[places::gens()])?;
+
     let gen_path = places::gens().join("gen.toml");
-    match std::fs::write(&gen_path, &user_gen_string) {
+    match std::fs::write(&gen_path, content) {
         Ok(_) => info!("Wrote generation file"),
         Err(e) => {
             error!("Failed to write generation file!");
@@ -236,8 +337,10 @@ pub fn commit(msg: &str) -> Result<String, io::Error> {
         }
     };
 
-    let repo = git::repo();
-    let hash = repo.commit(msg)?;
+    let number = numbers::next()?;
+
+    let repo = store::store()?;
+    let hash = repo.commit(&numbers::message(msg, number))?;
     
     if hash.is_empty() {
         warning!("No changes to commit");
@@ -246,141 +349,331 @@ pub fn commit(msg: &str) -> Result<String, io::Error> {
 
     set_current_hash(&hash, true)?;
 
+    info!("Committed as generation {}", number);
+
     Ok(hash)
 }
 
-fn get_order(gen: &Generation) -> Result<Vec<String>, io::Error> {
-    let return_order = {
-        let path = places::base_user().join("manager_order.toml");
+/// Whether two generation files declare the same thing. (Managers can be written in any order.)
+fn same_generation(a: &str, b: &str) -> bool {
+    match (toml::from_str::<Generation>(a), toml::from_str::<Generation>(b)) {
+        (Ok(a), Ok(b)) => a == b,
+        _ => a == b,
+    }
+}
 
-        if path.exists() {
-            info!("Reading order rules from manager_order.toml...");
+fn load_manager_for_build(man: &str, op: Operation, dry_run: bool) -> Result<Manager, io::Error> {
+    let mut manager = load_manager(man)?;
 
-            let order_obj: ManagerOrder = match toml::from_str(&std::fs::read_to_string(&path)?) {
-                Ok(o) => o,
-                Err(e) => {
-                    error!("Failed to deserialize manager_order.toml!");
-                    error!("TOML Error: {e:#?}");
+    if dry_run {
+        info!("{} ({}):", man, op.name());
+    }
 
-                    return Err(custom_error("Failed to deserialize manager_order.toml!"));
-                }
-            };
+    manager.set_dry_run(dry_run);
 
-            let mut order: Vec<String> = order_obj.begin.clone();
+    Ok(manager)
+}
 
-            for k in gen.managers.keys() {
-                if order_obj.begin.contains(k) || order_obj.end.contains(k) {
-                    continue;
-                }
+fn run_journaled(
+    man: &Manager,
+    man_name: &str,
+    op: Operation,
+    items: &[Item],
+    previous: &[Item],
+    journal: &Mutex<&mut Journal>,
+) -> Result<(), io::Error> {
+    let batches = man.batches(items);
+    let previous_batches = man.batches(previous);
+
+    let finished: Vec<usize> = {
+        let journal = journal.lock().unwrap();
+
+        (0..batches.len())
+            .filter(|x| journal.is_done(man_name, op, *x))
+            .collect()
+    };
 
-                order.push(k.to_string());
-            }
+    if !finished.is_empty() {
+        info!(
+            "Skipping {} already finished {} batch(es) of '{}'",
+            finished.len(),
+            op.name(),
+            man_name
+        );
+    }
 
-            order.extend(order_obj.end);
+    man.run_operation(
+        op,
+        items,
+        |x| finished.contains(&x),
+        |x| {
+            let previous = previous_batches.get(x).map(|x| x.as_slice()).unwrap_or(&[]);
 
-            let mut dup_track: HashMap<String, usize> = HashMap::new();
+            journal.lock().unwrap().record(man_name, op, x, &batches[x], previous)
+        },
+    )
+}
 
-            for o in order.iter() {
-                if dup_track.get(o) == None {
-                    dup_track.insert(o.to_string(), 1);
+fn run_steps(steps: &[&PlanStep], dry_run: bool, journal: &Mutex<&mut Journal>) -> Result<(), io::Error> {
+    for step in steps.iter() {
+        let man = load_manager_for_build(&step.manager, step.operation, dry_run)?;
 
-                    continue;
-                }
+        run_journaled(&man, &step.manager, step.operation, &step.items, &step.previous, journal)?;
+    }
 
-                *dup_track.get_mut(o).unwrap() += 1;
-            }
+    Ok(())
+}
 
-            for (key, value) in dup_track.into_iter() {
-                if value == 1 {
-                    continue;
-                }
+fn execute_plan(
+    plan: &BuildPlan,
+    dry_run: bool,
+    jobs: usize,
+    journal: &mut Journal,
+) -> Result<HashMap<String, Duration>, io::Error> {
+    let journal = Mutex::new(journal);
 
-                warning!("Duplicates in manager_order.toml! (Found {value} of: '{key}')");
-            }
+    if jobs > 1 {
+        return execute_plan_parallel(plan, dry_run, jobs, &journal);
+    }
 
-            order
-                .into_iter()
-                .filter(|x| gen.managers.contains_key(x))
-                .collect()
-        } else {
-            gen.managers
-                .keys()
-                .into_iter()
-                .map(|x| x.to_string())
-                .collect()
-        }
-    };
+    let mut timings: HashMap<String, Duration> = HashMap::new();
+
+    for step in plan.steps.iter() {
+        let started = Instant::now();
+
+        run_steps(&[step], dry_run, &journal)?;
 
-    Ok(return_order)
+        *timings.entry(step.manager.to_string()).or_default() += started.elapsed();
+    }
+
+    Ok(timings)
 }
 
-fn apply_diffs(built_gen: &Generation, curr_gen: &Generation) -> Result<(), io::Error> {
-    let curr_order: Vec<String> = get_order(curr_gen)?;
+/// Run up to `jobs` managers at once. A manager only starts once every manager it has to run
+/// after (see `order::dependencies()`) is done. Output is held back and printed per manager.
+fn execute_plan_parallel(
+    plan: &BuildPlan,
+    dry_run: bool,
+    jobs: usize,
+    journal: &Mutex<&mut Journal>,
+) -> Result<HashMap<String, Duration>, io::Error> {
+    // Every manager with its steps, in the order they show up in the plan.
+    let mut waiting: Vec<(String, Vec<&PlanStep>)> = Vec::new();
+
+    for step in plan.steps.iter() {
+        match waiting.iter_mut().find(|(name, _)| *name == step.manager) {
+            Some((_, steps)) => steps.push(step),
+            None => waiting.push((step.manager.to_string(), vec![step])),
+        };
+    }
+
+    let names: BTreeSet<String> = waiting.iter().map(|(name, _)| name.to_string()).collect();
 
-    for i in curr_order.iter() {
-        let man = load_manager(i)?;
+    // Order over every manager of both generations, so one with nothing to do still links the
+    // ones before and after it.
+    let mut all = names.clone();
 
-        let curr_items = curr_gen.managers.get(i).unwrap();
+    for hash in plan.from.iter().chain([&plan.to]) {
+        all.extend(get_gen_from_hash(hash)?.managers.into_keys());
+    }
 
-        match built_gen.managers.get(i) {
-            Some(built_items) => {
-                let diffs = history(&built_items.items, &curr_items.items);
+    let graph = order::restrict(&order::dependencies(&all)?, &names);
 
-                let mut to_install: Vec<String> = Vec::new();
-                let mut to_remove: Vec<String> = Vec::new();
+    let mut timings: HashMap<String, Duration> = HashMap::new();
+    let mut finished: BTreeSet<String> = BTreeSet::new();
+    let mut failed: Vec<(String, io::Error)> = Vec::new();
 
-                for j in diffs.iter() {
-                    match j.mode {
-                        HistoryMode::Add => to_install.push(j.line.to_string()),
-                        HistoryMode::Remove => to_remove.push(j.line.to_string()),
-                    };
-                }
+    thread::scope(|scope| {
+        let (tx, rx) = mpsc::channel();
+        let mut running: usize = 0;
+
+        loop {
+            while failed.is_empty() && running < jobs {
+                let ready = waiting.iter().position(|(name, _)| {
+                    graph
+                        .iter()
+                        .all(|(from, to)| !to.contains(name) || finished.contains(from))
+                });
+
+                let (name, steps) = match ready {
+                    Some(i) => waiting.remove(i),
+                    None => break,
+                };
+
+                info!("Starting: {}", name);
 
-                man.remove(&to_remove)?;
-                man.add(&to_install)?;
+                let tx = tx.clone();
+
+                scope.spawn(move || {
+                    let started = Instant::now();
+
+                    let (result, lines) = output::capture(|| run_steps(&steps, dry_run, journal));
+
+                    tx.send((name, result, lines, started.elapsed())).unwrap();
+                });
+
+                running += 1;
             }
-            None => {
-                man.add(&curr_items.items)?;
+
+            if running == 0 {
+                break;
             }
+
+            let (name, result, lines, elapsed) = rx.recv().unwrap();
+
+            running -= 1;
+
+            output::replay(&name, lines);
+
+            match result {
+                Ok(_) => {
+                    info!("Finished: {}", name);
+
+                    timings.insert(name.to_string(), elapsed);
+                    finished.insert(name);
+                }
+                Err(e) => {
+                    error!("Failed: {}", name);
+
+                    failed.push((name, e));
+                }
+            };
         }
+    });
+
+    if let Some((_, e)) = failed.into_iter().next() {
+        for (name, _) in waiting.iter() {
+            warning!("Skipped because of the failure: {}", name);
+        }
+
+        return Err(e);
     }
 
-    let built_order: Vec<String> = get_order(built_gen)?;
+    Ok(timings)
+}
 
-    for i in built_order.iter() {
-        let built_items = built_gen.managers.get(i).unwrap();
+fn run_build_hook(hook_name: &str, dry_run: bool) -> Result<(), io::Error> {
+    if dry_run {
+        hook::dry_run(hook_name);
 
-        match curr_gen.managers.get(i) {
-            Some(_) => (),
-            None => {
-                let man = load_manager(i)?;
-                man.remove(&built_items.items)?;
-            }
-        };
+        return Ok(());
     }
 
-    Ok(())
+    hook::run(hook_name)
+}
+
+fn resume_journal(built_hash: &Option<String>, current_hash: &str) -> Result<Journal, io::Error> {
+    let journal = match Journal::load()? {
+        Some(o) => o,
+        None => {
+            error!("There is no unfinished build to resume!");
+            return Err(custom_error("No unfinished build to resume!"));
+        }
+    };
+
+    if journal.from != *built_hash || journal.to != current_hash {
+        error!("The unfinished build was for a different generation, cannot resume it!");
+        note!("Run the build without '--resume' to start over.");
+
+        return Err(custom_error("Build journal does not match the generations!"));
+    }
+
+    info!("Resuming unfinished build... ({} batch(es) already done)", journal.done.len());
+
+    Ok(journal)
 }
 
-fn apply_full(curr_gen: &Generation) -> Result<(), io::Error> {
-    let curr_order = get_order(curr_gen)?;
+/// Undo every step the journal recorded from entry `since` onwards, newest first.
+fn compensate(journal: &mut Journal, since: usize) -> Result<(), io::Error> {
+    let to_revert: Vec<JournalEntry> = journal.done.split_off(since);
+
+    if to_revert.is_empty() {
+        note!("Nothing to revert, no changes were made by this build.");
+
+        return journal.save();
+    }
+
+    warning!("Build failed, reverting {} completed step(s)...", to_revert.len());
 
-    for i in curr_order.iter() {
-        let curr_items = curr_gen.managers.get(i).unwrap();
+    let mut reverted: Vec<&JournalEntry> = Vec::new();
+    let mut failed: Vec<&JournalEntry> = Vec::new();
 
-        let man = load_manager(i)?;
+    for entry in to_revert.iter().rev() {
+        // Reverting an update means updating back to what the items looked like before.
+        let items = match entry.phase {
+            Operation::Update => &entry.previous,
+            _ => &entry.items,
+        };
+
+        let result = load_manager(&entry.manager).and_then(|man| {
+            man.run_operation(entry.phase.inverse(), items, |_| false, |_| Ok(()))
+        });
 
-        man.add(&curr_items.items)?;
+        match result {
+            Ok(_) => reverted.push(entry),
+            Err(_) => failed.push(entry),
+        };
     }
 
-    Ok(())
+    println!();
+
+    info!("Reverted:");
+
+    for i in reverted.iter() {
+        generic!("{}: {} {}", i.manager, i.phase.inverse().name(), join_items(&i.items));
+    }
+
+    if !failed.is_empty() {
+        println!();
+
+        error!("Failed to revert (the system may not match any generation):");
+
+        for i in failed.iter() {
+            generic!("{}: {} {}", i.manager, i.phase.inverse().name(), join_items(&i.items));
+        }
+
+        // Whatever could not be reverted is still applied, so keep it in the journal.
+        journal
+            .done
+            .extend(failed.into_iter().rev().cloned());
+        journal.save()?;
+
+        return Err(custom_error("Failed to revert build!"));
+    }
+
+    match journal.done.is_empty() {
+        true => journal.finish(),
+        false => journal.save(),
+    }
 }
 
-pub fn build() -> Result<(), io::Error> {
-    
+fn join_items(items: &[Item]) -> String {
+    items
+        .iter()
+        .map(|x| x.name.to_string())
+        .collect::<Vec<String>>()
+        .join(" ")
+}
+
+pub struct BuildOptions {
+    pub dry_run: bool,
+    pub resume: bool,
+    pub atomic: bool,
+    pub jobs: usize,
+}
 
-    hook::run("pre_build")?;
+pub fn build(options: &BuildOptions) -> Result<(), io::Error> {
+    let (plan, built_gen, curr_gen) = plan_with_gens()?;
 
+    run_plan(&plan, built_gen.as_ref(), &curr_gen, options)
+}
+
+/// Compute the plan `build()` would execute, without executing anything.
+pub fn plan() -> Result<BuildPlan, io::Error> {
+    Ok(plan_with_gens()?.0)
+}
+
+fn plan_with_gens() -> Result<(BuildPlan, Option<Generation>, Generation), io::Error> {
     let curr_gen = match gen(ConfigSide::System) {
         Ok(o) => o,
         Err(e) => return Err(e),
@@ -394,85 +687,372 @@ pub fn build() -> Result<(), io::Error> {
         }
     };
 
-    match get_built_hash() {
-        Ok(built_hash) => {
-            let built_gen = get_gen_from_hash(&built_hash)?;
+    let built_hash = get_built_hash().ok();
+
+    let built_gen = match built_hash {
+        Some(ref built_hash) => Some(get_gen_from_hash(built_hash)?),
+        None => None,
+    };
+
+    let plan = BuildPlan::new(
+        built_hash,
+        &current_hash,
+        built_gen.as_ref().unwrap_or(&Generation::default()),
+        &curr_gen,
+    )?;
+
+    Ok((plan, built_gen, curr_gen))
+}
+
+/// Execute a plan made earlier by `plan()`, as long as nothing has been built since.
+pub fn apply(path: &Path, options: &BuildOptions) -> Result<(), io::Error> {
+    let plan = BuildPlan::load(path)?;
 
-            apply_diffs(&built_gen, &curr_gen)?;
+    let built_hash = get_built_hash().ok();
 
-            println!("");
-            println!("");
-            println!("");
+    if plan.from != built_hash {
+        error!("The built generation has changed since the plan was made, refusing to apply it!");
+        generic!("Plan was made from: {}", plan.from.as_deref().unwrap_or("(nothing built)"));
+        generic!("Currently built: {}", built_hash.as_deref().unwrap_or("(nothing built)"));
+
+        return Err(custom_error("Build plan is out of date!"));
+    }
+
+    let built_gen = match built_hash {
+        Some(ref built_hash) => Some(get_gen_from_hash(built_hash)?),
+        None => None,
+    };
+
+    let curr_gen = get_gen_from_hash(&plan.to)?;
+
+    run_plan(&plan, built_gen.as_ref(), &curr_gen, options)
+}
+
+fn run_plan(
+    plan: &BuildPlan,
+    built_gen: Option<&Generation>,
+    curr_gen: &Generation,
+    options: &BuildOptions,
+) -> Result<(), io::Error> {
+    let dry_run = options.dry_run;
+
+    if dry_run {
+        note!("Dry run: nothing will be executed, printing the commands instead.");
+    }
+
+    run_build_hook("pre_build", dry_run)?;
+
+    let mut journal = match options.resume {
+        true => resume_journal(&plan.from, &plan.to)?,
+        false => {
+            if Journal::load()?.is_some() {
+                warning!("A previous build did not finish, starting over! (Use '--resume' to continue it instead.)");
+            }
+
+            Journal::new(plan.from.clone(), &plan.to)
+        }
+    };
+
+    journal.set_persist(!dry_run);
+    journal.save()?;
+
+    let done_before = journal.done.len();
+
+    let timings = match execute_plan(plan, dry_run, options.jobs, &mut journal) {
+        Ok(o) => o,
+        Err(e) => {
+            if options.atomic {
+                compensate(&mut journal, done_before)?;
+            }
+
+            return Err(e);
+        }
+    };
+
+    match built_gen {
+        Some(built_gen) => {
+            println!();
+            println!();
+            println!();
 
             info!("#################");
             info!("#    SUMMARY    #");
             info!("#################");
 
-            println!("");
+            println!();
 
-            note!("Summary printing not yet implemented for Git-based system");
+            match dry_run {
+                true => BuildSummary::new(built_gen, curr_gen, &HashMap::new()).print(),
+                false => BuildSummary::new(built_gen, curr_gen, &timings).print(),
+            };
 
-            println!("");
-            println!("");
+            println!();
+            println!();
         }
-        Err(_) => {
-            apply_full(&curr_gen)?;
+        None => {
             note!("There is no summary. (First time building.)");
         }
     };
 
-    set_built_hash(&current_hash, true)?;
+    if dry_run {
+        run_build_hook("post_build", dry_run)?;
+
+        return Ok(());
+    }
+
+    set_built_hash(&plan.to, true)?;
+    journal.finish()?;
 
-    hook::run("post_build")?;
+    run_build_hook("post_build", dry_run)?;
 
     Ok(())
 }
 
-pub fn rollback(by: isize, verbose: bool) -> Result<(), io::Error> {
-    
+/// Check the built generation against the system. Returns the drift (only undeclared items or
+/// unverifiable managers) when nothing declared is missing.
+pub fn verify() -> Result<Drift, io::Error> {
+    let built_hash = match get_built_hash() {
+        Ok(o) => o,
+        Err(_) => {
+            error!("Nothing has been built yet, so there is nothing to verify!");
+            return Err(custom_error("Nothing has been built yet!"));
+        }
+    };
 
-    let repo = git::repo();
-    let _current_hash = get_current_hash()?;
-    
-    let log = repo.log(None)?;
-    
-    if by >= log.len() as isize {
-        error!("Cannot rollback that far!");
-        return Err(custom_error("Rollback out of range!"));
+    let built_gen = get_gen_from_hash(&built_hash)?;
+
+    let drift = Drift::detect(&built_gen)?;
+
+    println!();
+
+    drift.print();
+
+    if drift.missing_count() > 0 {
+        error!(
+            "Found drift: {} declared item(s) are not installed!",
+            drift.missing_count()
+        );
+
+        return Err(custom_error("Found drift!"));
     }
-    
-    let target_index = by as usize;
-    if target_index >= log.len() {
-        error!("Rollback target out of range!");
-        return Err(custom_error("Rollback target out of range!"));
+
+    Ok(drift)
+}
+
+/// Install whatever from the built generation is missing, and optionally remove what is installed
+/// but not declared. Uses the same ordering and hooks as a build, but leaves 'built' alone.
+pub fn repair(remove_others: bool, dry_run: bool) -> Result<(), io::Error> {
+    let built_hash = match get_built_hash() {
+        Ok(o) => o,
+        Err(_) => {
+            error!("Nothing has been built yet, so there is nothing to repair!");
+            return Err(custom_error("Nothing has been built yet!"));
+        }
+    };
+
+    let built_gen = get_gen_from_hash(&built_hash)?;
+
+    let drift = Drift::detect(&built_gen)?;
+
+    drift.print();
+
+    let mut remove_others = remove_others && drift.others_count() > 0;
+
+    if remove_others {
+        for m in drift.managers.iter().filter(|x| !x.others.is_empty()) {
+            print_entry!(format!("{} (not declared)", m.manager), m.others);
+        }
+
+        if !dry_run {
+            remove_others = crate::bool_question("Remove the items that are not declared?", false);
+        }
     }
-    
-    let target_hash = &log[target_index].0;
-    
-    repo.checkout(target_hash)?;
-    set_current_hash(target_hash, verbose)?;
-    
+
+    let mut steps: Vec<PlanStep> = Vec::new();
+
+    for m in drift.managers.iter() {
+        if remove_others && !m.others.is_empty() {
+            let others = m.others.iter().map(|x| Item::new(x)).collect();
+
+            steps.push(PlanStep::new(&m.manager, Operation::Remove, others)?);
+        }
+
+        if !m.missing.is_empty() {
+            steps.push(PlanStep::new(&m.manager, Operation::Add, m.missing.clone())?);
+        }
+    }
+
+    if steps.is_empty() {
+        return Ok(());
+    }
+
+    let plan = BuildPlan {
+        from: Some(built_hash.to_string()),
+        to: built_hash,
+        steps,
+    };
+
+    if dry_run {
+        note!("Dry run: nothing will be executed, printing the commands instead.");
+    }
+
+    run_build_hook("pre_build", dry_run)?;
+
+    // Repairs aren't resumable, they just get recomputed, so the journal stays in memory.
+    execute_plan(&plan, dry_run, 1, &mut Journal::new(plan.from.clone(), &plan.to))?;
+
+    run_build_hook("post_build", dry_run)?;
+
     Ok(())
 }
 
-pub fn latest(verbose: bool) -> Result<(), io::Error> {
-    
+/// Go back `by` generations from the current one.
+///
+/// Like switching to an older generation on NixOS, this never rewrites history. The target is
+/// committed again as a new generation, and that becomes the current one.
+pub fn rollback(by: isize, discard: bool, verbose: bool) -> Result<(), io::Error> {
+    let gens = numbers::generations()?;
 
-    let repo = git::repo();
-    let log = repo.log(Some(1))?;
-    
-    if log.is_empty() {
-        error!("No generations found!");
-        return Err(custom_error("No generations found!"));
+    let current_hash = match get_current_hash() {
+        Ok(o) => o,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => {
+            error!("No current generation found!");
+            return Err(custom_error("No current generation found!"));
+        }
+        Err(e) => return Err(e),
+    };
+
+    let current = match gens.iter().find(|x| x.hash == current_hash) {
+        Some(s) => s.number,
+        None => {
+            error!("The current generation is not a committed one!");
+            return Err(custom_error("Current generation not found!"));
+        }
+    };
+
+    let target = match usize::try_from(by).ok().and_then(|x| current.checked_sub(x)) {
+        Some(s) if s > 0 => s,
+        _ => {
+            error!("Cannot rollback that far!");
+            return Err(custom_error("Rollback out of range!"));
+        }
+    };
+
+    let dirty = uncommitted_changes()?;
+
+    if dirty {
+        if !discard {
+            error!("The generation file in the state directory has uncommitted changes!");
+            note!("Commit them with 'gen commit' first, or pass '--discard' to throw them away.");
+
+            return Err(custom_error("Uncommitted changes!"));
+        }
+
+        warning!("Discarding uncommitted changes to the generation file...");
+    }
+
+    let target_hash = numbers::hash(target)?;
+
+    let content = store::store()?.file_at(&target_hash, &gen_file_in_repo())?;
+
+    // Nothing to commit when the latest generation already is the target.
+    let latest = &gens[0];
+
+    if same_generation(&store::store()?.file_at(&latest.hash, &gen_file_in_repo())?, &content) {
+        if verbose {
+            info!("Generation {} is the same as generation {}", latest.number, target);
+        }
+
+        // Nothing gets committed, so the discarded changes have to be thrown away here.
+        if dirty {
+            if let Err(e) = std::fs::write(places::gens().join("gen.toml"), &content) {
+                error!("Failed to write generation file!");
+                return Err(e);
+            }
+        }
+
+        return set_current_hash(&latest.hash, verbose);
+    }
+
+    commit_content(&content, &format!("Rollback to generation {target}"))?;
+
+    if verbose {
+        info!("Rolled back to the contents of generation {}", target);
     }
     
-    let latest_hash = &log[0].0;
-    repo.checkout(latest_hash)?;
-    set_current_hash(latest_hash, verbose)?;
+    Ok(())
+}
+
+/// Make the newest generation the current one.
+pub fn latest(verbose: bool) -> Result<(), io::Error> {
+    let gens = numbers::generations()?;
+
+    let latest = match gens.first() {
+        Some(s) => s,
+        None => {
+            error!("No generations found!");
+            return Err(custom_error("No generations found!"));
+        }
+    };
+
+    set_current_hash(&latest.hash, verbose)?;
     
     Ok(())
 }
 
+/// The current generation, if the generation file in the state directory doesn't match it.
+pub fn working_copy_mismatch() -> Result<Option<String>, io::Error> {
+    // Nothing to compare against before the first commit.
+    if !places::gens().join("current").exists() {
+        return Ok(None);
+    }
+
+    let hash = get_current_hash()?;
+
+    let committed = match store::store()?.file_at(&hash, &gen_file_in_repo()) {
+        Ok(o) => Some(o),
+        Err(e) if e.kind() == io::ErrorKind::NotFound => None,
+        Err(e) => return Err(e),
+    };
+
+    let working = match std::fs::read_to_string(places::gens().join("gen.toml")) {
+        Ok(o) => Some(o),
+        Err(e) if e.kind() == io::ErrorKind::NotFound => None,
+        Err(e) => {
+            error!("Failed to read generation file!");
+            return Err(e);
+        }
+    };
+
+    if committed == working {
+        return Ok(None);
+    }
+
+    Ok(Some(match numbers::number(&hash)? {
+        Some(s) => format!("generation {s}"),
+        None => format!("revision {hash}"),
+    }))
+}
+
+/// Whether the generation file in the state directory was changed since the newest generation.
+fn uncommitted_changes() -> Result<bool, io::Error> {
+    let latest = match numbers::generations()?.into_iter().next() {
+        Some(s) => s,
+        None => return Ok(false),
+    };
+
+    let committed = store::store()?.file_at(&latest.hash, &gen_file_in_repo())?;
+
+    match std::fs::read_to_string(places::gens().join("gen.toml")) {
+        Ok(o) => Ok(o != committed),
+        Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(true),
+        Err(e) => {
+            error!("Failed to read generation file!");
+            Err(e)
+        }
+    }
+}
+
 pub fn set_current_hash(hash: &str, verbose: bool) -> Result<(), io::Error> {
     let current_path = places::gens().join("current");
     
@@ -508,9 +1088,6 @@ pub fn set_built_hash(hash: &str, verbose: bool) -> Result<(), io::Error> {
 }
 
 pub fn list() -> Result<Vec<(String, String, bool, bool)>, io::Error> {
-    let repo = git::repo();
-    let commits = repo.log(None)?;
-    
     let current_hash = match get_current_hash() {
         Ok(hash) => hash,
         Err(_) => String::new(),
@@ -523,12 +1100,12 @@ pub fn list() -> Result<Vec<(String, String, bool, bool)>, io::Error> {
     
     let mut gens: Vec<(String, String, bool, bool)> = Vec::new();
     
-    for (i, (hash, message)) in commits.iter().enumerate() {
+    for i in numbers::generations()?.into_iter() {
         gens.push((
-            format!("{}", i + 1),
-            message.clone(),
-            hash == &current_hash,
-            hash == &built_hash,
+            i.number.to_string(),
+            i.message,
+            i.hash == current_hash,
+            i.hash == built_hash,
         ));
     }
     
@@ -537,17 +1114,13 @@ pub fn list() -> Result<Vec<(String, String, bool, bool)>, io::Error> {
 
 pub fn list_print() -> Result<(), io::Error> {
     let list_items = list()?;
-    
-    let mut max_digits: usize = 0;
-    
-    if list_items.len() > 0 {
-        max_digits = list_items[list_items.len() - 1]
-            .0
-            .to_string()
-            .trim()
-            .len();
+
+    if let Some(p) = places::profile() {
+        info!("Generations of profile '{p}':");
     }
     
+    let max_digits = list_items.iter().map(|x| x.0.trim().len()).max().unwrap_or(0);
+    
     for i in list_items.iter() {
         let mut misc_text = String::new();
         
@@ -588,21 +1161,7 @@ pub fn list_print() -> Result<(), io::Error> {
 }
 
 pub fn get_hash_from_number(num: usize) -> Result<String, io::Error> {
-    let repo = git::repo();
-    let commits = repo.log(None)?;
-    
-    if num == 0 || num > commits.len() {
-        error!("Generation number {} out of range!", num);
-        return Err(custom_error("Generation number out of range!"));
-    }
-    
-    Ok(commits[num - 1].0.clone())
-}
-
-pub fn current_gen() -> Result<PathBuf, io::Error> {
-    let _current_hash = get_current_hash()?;
-    let gen_path = places::gens().join("gen.toml");
-    Ok(gen_path)
+    numbers::hash(num)
 }
 
 pub fn been_built() -> bool {
@@ -0,0 +1,85 @@
+use piglog::prelude::*;
+use piglog::*;
+use std::io;
+
+use super::gen_file_in_repo;
+use crate::library::*;
+use crate::store;
+
+/// Commit trailer that carries the generation number.
+pub const TRAILER: &str = "Rebos-Generation";
+
+/// A committed generation of the active profile.
+#[derive(Debug, Clone)]
+pub struct Numbered {
+    pub number: usize,
+    pub hash: String,
+    /// The first line of the commit message.
+    pub message: String,
+}
+
+/// Every generation of the active profile, newest first.
+///
+/// Numbers are stored in each commit, so they never change once given out. Commits from before
+/// that are counted up from the oldest one.
+pub fn generations() -> Result<Vec<Numbered>, io::Error> {
+    let log = store::store()?.log(None, Some(&gen_file_in_repo()))?;
+
+    let mut gens: Vec<Numbered> = Vec::new();
+    let mut last: usize = 0;
+
+    for (hash, message) in log.into_iter().rev() {
+        let number = match trailer(&message) {
+            Some(s) => s,
+            None => last + 1,
+        };
+
+        last = last.max(number);
+
+        gens.push(Numbered {
+            number,
+            hash,
+            message: message.lines().next().unwrap_or("").to_string(),
+        });
+    }
+
+    gens.reverse();
+
+    Ok(gens)
+}
+
+/// The number the next commit gets.
+pub fn next() -> Result<usize, io::Error> {
+    Ok(generations()?.iter().map(|x| x.number).max().unwrap_or(0) + 1)
+}
+
+/// A commit message carrying a generation number.
+pub fn message(message: &str, number: usize) -> String {
+    format!("{}\n\n{TRAILER}: {number}\n", message.trim_end())
+}
+
+pub fn hash(number: usize) -> Result<String, io::Error> {
+    match generations()?.into_iter().find(|x| x.number == number) {
+        Some(s) => Ok(s.hash),
+        None => {
+            error!("Generation {} does not exist!", number);
+            Err(custom_error("Generation number out of range!"))
+        }
+    }
+}
+
+/// The number of a committed revision. (`None` if it isn't a generation of the active profile.)
+pub fn number(hash: &str) -> Result<Option<usize>, io::Error> {
+    Ok(generations()?.into_iter().find(|x| x.hash == hash).map(|x| x.number))
+}
+
+fn trailer(message: &str) -> Option<usize> {
+    message.lines().rev().find_map(|line| {
+        let (key, value) = line.split_once(':')?;
+
+        match key.trim() == TRAILER {
+            true => value.trim().parse().ok(),
+            false => None,
+        }
+    })
+}
@@ -0,0 +1,320 @@
+use piglog::prelude::*;
+use piglog::*;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::io;
+
+use super::Generation;
+use crate::diagnostic;
+use crate::library::*;
+use crate::management::{get_managers, load_manager};
+use crate::places;
+
+#[derive(PartialEq, Serialize, Deserialize, JsonSchema, Debug, Default)]
+#[serde(deny_unknown_fields, default)]
+pub struct ManagerOrder {
+    pub begin: Vec<String>,
+    pub end: Vec<String>,
+}
+
+/// Edges of the ordering graph. (`a -> b` means `a` has to run before `b`.)
+pub type Graph = BTreeMap<String, BTreeSet<String>>;
+
+/// The order to run the managers of a generation in.
+///
+/// Managers can declare `after`/`before` lists in their own file, and `manager_order.toml`
+/// can pin managers to the `begin` or `end`. Everything is sorted topologically, ties are
+/// broken alphabetically so the order is the same on every run.
+pub fn get_order(gen: &Generation) -> Result<Vec<String>, io::Error> {
+    let names: BTreeSet<String> = gen.managers.keys().map(|x| x.to_string()).collect();
+
+    let graph = dependencies(&names)?;
+
+    sort(&names, &graph)
+}
+
+/// The ordering graph between the given managers.
+pub fn dependencies(names: &BTreeSet<String>) -> Result<Graph, io::Error> {
+    let mut graph: Graph = names
+        .iter()
+        .map(|x| (x.to_string(), BTreeSet::new()))
+        .collect();
+
+    add_order_rules(names, &mut graph)?;
+    add_manager_rules(names, &mut graph)?;
+
+    // Fail on cycles here as well, so callers never get a graph that can't be run.
+    sort(names, &graph)?;
+
+    Ok(graph)
+}
+
+/// The graph between `names` only. Managers outside of `names` are bridged over, so `a -> b`
+/// if `b` can be reached from `a` through them. (Idle managers must not break a chain.)
+pub fn restrict(graph: &Graph, names: &BTreeSet<String>) -> Graph {
+    let mut restricted: Graph = names
+        .iter()
+        .map(|x| (x.to_string(), BTreeSet::new()))
+        .collect();
+
+    for name in names.iter() {
+        let mut seen: BTreeSet<&String> = BTreeSet::new();
+        let mut stack: Vec<&String> = graph.get(name).into_iter().flatten().collect();
+
+        while let Some(next) = stack.pop() {
+            if !seen.insert(next) {
+                continue;
+            }
+
+            // Whatever comes after a kept manager already waits for it.
+            if names.contains(next) {
+                add_edge(&mut restricted, name, next);
+
+                continue;
+            }
+
+            stack.extend(graph.get(next).into_iter().flatten());
+        }
+    }
+
+    restricted
+}
+
+fn add_order_rules(names: &BTreeSet<String>, graph: &mut Graph) -> Result<(), io::Error> {
+    let path = places::base_user().join("manager_order.toml");
+
+    if !path.exists() {
+        return Ok(());
+    }
+
+    info!("Reading order rules from manager_order.toml...");
+
+    let order_string = std::fs::read_to_string(&path)?;
+
+    let order_obj: ManagerOrder = match toml::from_str(&order_string) {
+        Ok(o) => o,
+        Err(e) => {
+            diagnostic::toml_error(&path, &order_string, &e);
+
+            return Err(custom_error("Failed to deserialize manager_order.toml!"));
+        }
+    };
+
+    let mut dup_track: HashMap<&String, usize> = HashMap::new();
+
+    for o in order_obj.begin.iter().chain(order_obj.end.iter()) {
+        *dup_track.entry(o).or_default() += 1;
+    }
+
+    for (key, value) in dup_track.into_iter() {
+        if value == 1 {
+            continue;
+        }
+
+        warning!("Duplicates in manager_order.toml! (Found {value} of: '{key}')");
+    }
+
+    let begin: Vec<&String> = remove_array_duplicates(&order_obj.begin)
+        .iter()
+        .filter_map(|x| names.get(x))
+        .collect();
+
+    let end: Vec<&String> = remove_array_duplicates(&order_obj.end)
+        .iter()
+        .filter_map(|x| names.get(x))
+        .filter(|x| !begin.contains(x))
+        .collect();
+
+    for (i, b) in begin.iter().enumerate() {
+        for other in names.iter() {
+            if !begin[..=i].contains(&other) {
+                add_edge(graph, b, other);
+            }
+        }
+    }
+
+    for (i, e) in end.iter().enumerate() {
+        for other in names.iter() {
+            if !end[i..].contains(&other) && !begin.contains(&other) {
+                add_edge(graph, other, e);
+            }
+        }
+    }
+
+    Ok(())
+}
+
+fn add_manager_rules(names: &BTreeSet<String>, graph: &mut Graph) -> Result<(), io::Error> {
+    let existing = get_managers()?;
+
+    let mut errors: Vec<String> = Vec::new();
+
+    for name in names.iter() {
+        let man = load_manager(name)?;
+
+        for (other, after) in man
+            .after
+            .iter()
+            .map(|x| (x, true))
+            .chain(man.before.iter().map(|x| (x, false)))
+        {
+            let field = if after { "after" } else { "before" };
+
+            if !existing.contains(other) {
+                errors.push(format!(
+                    "Manager '{name}' has '{other}' in '{field}', but there is no such manager!"
+                ));
+
+                continue;
+            }
+
+            if other == name {
+                errors.push(format!("Manager '{name}' has itself in '{field}'!"));
+
+                continue;
+            }
+
+            // Managers that aren't part of this generation don't take part in ordering.
+            if !names.contains(other) {
+                continue;
+            }
+
+            match after {
+                true => add_edge(graph, other, name),
+                false => add_edge(graph, name, other),
+            };
+        }
+    }
+
+    if !errors.is_empty() {
+        for e in errors.iter() {
+            error!("{}", e);
+        }
+
+        return Err(custom_error("Invalid manager ordering rules!"));
+    }
+
+    Ok(())
+}
+
+fn add_edge(graph: &mut Graph, from: &str, to: &str) {
+    graph
+        .entry(from.to_string())
+        .or_default()
+        .insert(to.to_string());
+}
+
+fn sort(names: &BTreeSet<String>, graph: &Graph) -> Result<Vec<String>, io::Error> {
+    let mut in_degree: BTreeMap<&String, usize> = names.iter().map(|x| (x, 0)).collect();
+
+    for to in graph.values().flatten() {
+        *in_degree.get_mut(to).unwrap() += 1;
+    }
+
+    let mut ready: BTreeSet<&String> = in_degree
+        .iter()
+        .filter(|(_, d)| **d == 0)
+        .map(|(x, _)| *x)
+        .collect();
+
+    let mut order: Vec<String> = Vec::new();
+
+    while let Some(next) = ready.pop_first() {
+        order.push(next.to_string());
+
+        for to in graph.get(next).into_iter().flatten() {
+            let degree = in_degree.get_mut(to).unwrap();
+
+            *degree -= 1;
+
+            if *degree == 0 {
+                ready.insert(to);
+            }
+        }
+    }
+
+    if order.len() < names.len() {
+        let cycle = find_cycle(names, graph, &order);
+
+        error!("Manager ordering rules contain a cycle: {}", cycle.join(" -> "));
+
+        return Err(custom_error("Cycle in manager ordering rules!"));
+    }
+
+    Ok(order)
+}
+
+/// Find a cycle among the managers that could not be sorted.
+fn find_cycle(names: &BTreeSet<String>, graph: &Graph, sorted: &[String]) -> Vec<String> {
+    let remaining: BTreeSet<&String> = names.iter().filter(|x| !sorted.contains(x)).collect();
+
+    // Every remaining manager has a remaining predecessor, so walking backwards has to loop.
+    let mut path: Vec<&String> = vec![remaining.first().unwrap()];
+
+    loop {
+        let current = path[path.len() - 1];
+
+        let pred = remaining
+            .iter()
+            .find(|x| graph.get(**x).map(|t| t.contains(current)).unwrap_or(false))
+            .unwrap();
+
+        if let Some(start) = path.iter().position(|x| x == pred) {
+            let mut cycle: Vec<String> = path[start..].iter().rev().map(|x| x.to_string()).collect();
+
+            cycle.push(cycle[0].to_string());
+
+            return cycle;
+        }
+
+        path.push(pred);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn names(names: &[&str]) -> BTreeSet<String> {
+        names.iter().map(|x| x.to_string()).collect()
+    }
+
+    /// A graph with every one of `names` in it, and the given edges.
+    fn graph(names: &[&str], edges: &[(&str, &str)]) -> Graph {
+        let mut graph: Graph = names.iter().map(|x| (x.to_string(), BTreeSet::new())).collect();
+
+        for (from, to) in edges.iter() {
+            add_edge(&mut graph, from, to);
+        }
+
+        graph
+    }
+
+    #[test]
+    fn restrict_bridges_left_out_managers() {
+        let full = graph(&["aa", "bb", "cc"], &[("aa", "bb"), ("bb", "cc")]);
+
+        let restricted = restrict(&full, &names(&["aa", "cc"]));
+
+        assert_eq!(restricted, graph(&["aa", "cc"], &[("aa", "cc")]));
+    }
+
+    #[test]
+    fn restrict_keeps_direct_edges() {
+        let full = graph(&["aa", "bb", "cc", "dd", "xx"], &[("aa", "bb"), ("bb", "cc"), ("xx", "cc")]);
+
+        let restricted = restrict(&full, &names(&["aa", "bb", "cc", "dd"]));
+
+        assert_eq!(restricted, graph(&["aa", "bb", "cc", "dd"], &[("aa", "bb"), ("bb", "cc")]));
+    }
+
+    #[test]
+    fn restrict_drops_unconnected() {
+        let full = graph(&["aa", "bb", "cc"], &[("aa", "bb"), ("cc", "bb")]);
+
+        let restricted = restrict(&full, &names(&["aa", "cc"]));
+
+        assert_eq!(restricted, graph(&["aa", "cc"], &[]));
+    }
+}
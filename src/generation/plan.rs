@@ -0,0 +1,205 @@
+use colored::Colorize;
+use piglog::prelude::*;
+use piglog::*;
+use serde::{Deserialize, Serialize};
+use std::io;
+use std::path::Path;
+
+use super::order::get_order;
+use super::{Generation, Item};
+use crate::hook;
+use crate::library::*;
+use crate::management::{load_manager, Operation};
+
+#[derive(PartialEq, Serialize, Deserialize, Debug, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct PlanStep {
+    pub manager: String,
+    pub operation: Operation,
+    pub items: Vec<Item>,
+    /// What the items looked like before. (Only used by update steps, in the same order as `items`.)
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub previous: Vec<Item>,
+    /// Hooks that fire around this step. (Only the ones that existed when planning.)
+    #[serde(default)]
+    pub hooks: Vec<String>,
+}
+
+/// Everything a build is going to do, in the order it will be done.
+#[derive(PartialEq, Serialize, Deserialize, Debug)]
+#[serde(deny_unknown_fields)]
+pub struct BuildPlan {
+    /// The built hash this plan starts from. (None if nothing has been built yet.)
+    pub from: Option<String>,
+    /// The hash that will be built.
+    pub to: String,
+    pub steps: Vec<PlanStep>,
+}
+
+impl BuildPlan {
+    pub fn new(
+        from: Option<String>,
+        to: &str,
+        built_gen: &Generation,
+        curr_gen: &Generation,
+    ) -> Result<Self, io::Error> {
+        let mut steps: Vec<PlanStep> = Vec::new();
+
+        for i in get_order(curr_gen)?.iter() {
+            let curr_items = curr_gen.managers.get(i).unwrap();
+
+            match built_gen.managers.get(i) {
+                Some(built_items) => {
+                    let diffs = history(&built_items.items, &curr_items.items);
+
+                    let mut to_install: Vec<Item> = Vec::new();
+                    let mut to_remove: Vec<Item> = Vec::new();
+                    let mut to_update: Vec<Item> = Vec::new();
+                    let mut previous: Vec<Item> = Vec::new();
+
+                    for j in diffs.into_iter() {
+                        match j.mode {
+                            HistoryMode::Add => to_install.push(j.item),
+                            HistoryMode::Remove => to_remove.push(j.item),
+                            HistoryMode::Update => {
+                                to_update.push(j.item);
+                                previous.push(j.previous.unwrap());
+                            }
+                        };
+                    }
+
+                    let mut update = PlanStep::new(i, Operation::Update, to_update)?;
+                    update.previous = previous;
+
+                    steps.push(PlanStep::new(i, Operation::Remove, to_remove)?);
+                    steps.push(update);
+                    steps.push(PlanStep::new(i, Operation::Add, to_install)?);
+                }
+                None => steps.push(PlanStep::new(i, Operation::Add, curr_items.items.clone())?),
+            };
+        }
+
+        for i in get_order(built_gen)?.iter() {
+            if curr_gen.managers.contains_key(i) {
+                continue;
+            }
+
+            let built_items = built_gen.managers.get(i).unwrap();
+
+            steps.push(PlanStep::new(i, Operation::Remove, built_items.items.clone())?);
+        }
+
+        // Steps with nothing to do are left out, unless they have hooks. (Those have always run
+        // on every build, whether there were items or not.)
+        steps.retain(|x| !x.items.is_empty() || !x.hooks.is_empty());
+
+        Ok(Self {
+            from,
+            to: to.to_string(),
+            steps,
+        })
+    }
+
+    pub fn load(path: &Path) -> Result<Self, io::Error> {
+        let plan_string = match std::fs::read_to_string(path) {
+            Ok(o) => o,
+            Err(e) => {
+                error!("Failed to read build plan! ({})", path.display());
+                return Err(e);
+            }
+        };
+
+        let plan = match is_json(path) {
+            true => serde_json::from_str(&plan_string).map_err(|e| e.to_string()),
+            false => toml::from_str(&plan_string).map_err(|e| e.to_string()),
+        };
+
+        match plan {
+            Ok(o) => Ok(o),
+            Err(e) => {
+                error!("Failed to deserialize build plan:");
+                error!("{e}");
+                error!("Path: '{}'", path.display());
+
+                Err(custom_error("Failed to deserialize build plan!"))
+            }
+        }
+    }
+
+    /// Write the plan as JSON or TOML, depending on the file extension.
+    pub fn save(&self, path: &Path) -> Result<(), io::Error> {
+        let plan_string = match is_json(path) {
+            true => serde_json::to_string_pretty(self)
+                .map(|x| x + "\n")
+                .map_err(|e| e.to_string()),
+            false => toml::to_string(self).map_err(|e| e.to_string()),
+        };
+
+        let plan_string = match plan_string {
+            Ok(o) => o,
+            Err(e) => {
+                error!("Failed to convert build plan to string: {e}");
+                return Err(custom_error("Failed to convert build plan to string!"));
+            }
+        };
+
+        match std::fs::write(path, plan_string) {
+            Ok(_) => Ok(()),
+            Err(e) => {
+                error!("Failed to write build plan! ({})", path.display());
+                Err(e)
+            }
+        }
+    }
+
+    pub fn print(&self) {
+        info!(
+            "Plan: {} {} {}",
+            self.from.as_deref().unwrap_or("(nothing built)").bright_cyan(),
+            "->".bright_black().bold(),
+            self.to.bright_cyan()
+        );
+
+        println!();
+
+        for (i, step) in self.steps.iter().enumerate() {
+            info!("{}. {} {} ({} items)", i + 1, step.manager, step.operation.name(), step.items.len());
+
+            for h in step.hooks.iter() {
+                note!("Hook: {}", h);
+            }
+
+            for j in step.items.iter() {
+                match step.operation {
+                    Operation::Add => println!("{}", format!("+ {}", j).bright_green().bold()),
+                    Operation::Remove => println!("{}", format!("- {}", j).bright_red().bold()),
+                    Operation::Update => println!("{}", format!("~ {}", j).bright_yellow().bold()),
+                };
+            }
+        }
+    }
+}
+
+impl PlanStep {
+    pub fn new(manager: &str, operation: Operation, items: Vec<Item>) -> Result<Self, io::Error> {
+        let man = load_manager(manager)?;
+
+        let hooks = ["pre", "post"]
+            .into_iter()
+            .map(|x| format!("{}_{}_{}", x, man.hook_name, operation.name()))
+            .filter(|x| hook::path(x).exists())
+            .collect();
+
+        Ok(Self {
+            manager: manager.to_string(),
+            operation,
+            items,
+            previous: Vec::new(),
+            hooks,
+        })
+    }
+}
+
+fn is_json(path: &Path) -> bool {
+    path.extension().map(|x| x == "json").unwrap_or(false)
+}
@@ -0,0 +1,42 @@
+use std::path::PathBuf;
+
+use super::Generation;
+use crate::places;
+
+/// Where an item of the merged generation was declared.
+#[derive(PartialEq, Debug, Clone)]
+pub struct Provenance {
+    pub manager: String,
+    pub item: String,
+    /// The import chain that led to the declaring file, starting at the root file. (The last
+    /// entry is the declaring file itself.)
+    pub chain: Vec<String>,
+}
+
+impl Provenance {
+    pub fn file(&self) -> &str {
+        &self.chain[self.chain.len() - 1]
+    }
+}
+
+/// Remember where the items of a freshly read file came from.
+pub fn record(gen: &mut Generation, chain: &[PathBuf]) {
+    let chain: Vec<String> = chain.iter().map(|x| places::relative_to_user(x)).collect();
+
+    let mut managers: Vec<&String> = gen.managers.keys().collect();
+    managers.sort();
+
+    let mut provenance: Vec<Provenance> = Vec::new();
+
+    for m in managers.into_iter() {
+        for i in gen.managers.get(m).unwrap().items.iter() {
+            provenance.push(Provenance {
+                manager: m.to_string(),
+                item: i.name.to_string(),
+                chain: chain.clone(),
+            });
+        }
+    }
+
+    gen.provenance.extend(provenance);
+}
@@ -0,0 +1,169 @@
+use colored::Colorize;
+use piglog::prelude::*;
+use piglog::*;
+use std::collections::HashMap;
+use std::time::Duration;
+
+use crate::generation::Generation;
+use crate::library::*;
+
+#[derive(PartialEq, Debug, Clone, Copy)]
+pub enum ManagerChange {
+    Introduced,
+    Dropped,
+    Changed,
+    Unchanged,
+}
+
+#[derive(Debug)]
+pub struct ManagerSummary {
+    pub manager: String,
+    pub change: ManagerChange,
+    pub added: Vec<String>,
+    pub removed: Vec<String>,
+    pub updated: Vec<String>,
+    pub duration: Option<Duration>,
+}
+
+#[derive(Debug, Default)]
+pub struct BuildSummary {
+    pub managers: Vec<ManagerSummary>,
+}
+
+impl BuildSummary {
+    /// Build a summary out of the built and current generations, and the time each manager took.
+    pub fn new(
+        built_gen: &Generation,
+        curr_gen: &Generation,
+        timings: &HashMap<String, Duration>,
+    ) -> Self {
+        let history = history_gen(built_gen, curr_gen);
+
+        let mut managers: Vec<ManagerSummary> = history
+            .into_iter()
+            .map(|(manager, diffs)| {
+                let mut added: Vec<String> = Vec::new();
+                let mut removed: Vec<String> = Vec::new();
+                let mut updated: Vec<String> = Vec::new();
+
+                for d in diffs.into_iter() {
+                    match d.mode {
+                        HistoryMode::Add => added.push(d.item.to_string()),
+                        HistoryMode::Remove => removed.push(d.item.to_string()),
+                        HistoryMode::Update => updated.push(d.item.to_string()),
+                    };
+                }
+
+                added.sort();
+                removed.sort();
+                updated.sort();
+
+                let change = if !built_gen.managers.contains_key(&manager) {
+                    ManagerChange::Introduced
+                } else if !curr_gen.managers.contains_key(&manager) {
+                    ManagerChange::Dropped
+                } else if added.is_empty() && removed.is_empty() && updated.is_empty() {
+                    ManagerChange::Unchanged
+                } else {
+                    ManagerChange::Changed
+                };
+
+                ManagerSummary {
+                    duration: timings.get(&manager).copied(),
+                    manager,
+                    change,
+                    added,
+                    removed,
+                    updated,
+                }
+            })
+            .collect();
+
+        managers.sort_by(|a, b| a.manager.cmp(&b.manager));
+
+        Self { managers }
+    }
+
+    pub fn total_added(&self) -> usize {
+        self.managers.iter().map(|m| m.added.len()).sum()
+    }
+
+    pub fn total_removed(&self) -> usize {
+        self.managers.iter().map(|m| m.removed.len()).sum()
+    }
+
+    pub fn total_updated(&self) -> usize {
+        self.managers.iter().map(|m| m.updated.len()).sum()
+    }
+
+    pub fn total_duration(&self) -> Duration {
+        self.managers.iter().filter_map(|m| m.duration).sum()
+    }
+
+    pub fn print(&self) {
+        for m in self.managers.iter() {
+            let tag = match m.change {
+                ManagerChange::Introduced => format!(" {}", "[NEW]".bright_green().bold()),
+                ManagerChange::Dropped => format!(" {}", "[DROPPED]".bright_red().bold()),
+                ManagerChange::Changed | ManagerChange::Unchanged => String::new(),
+            };
+
+            let timing = match m.duration {
+                Some(d) => format!(" in {}", format_duration(d)),
+                None => String::new(),
+            };
+
+            info!(
+                "{}:{} (+{} -{} ~{}){}",
+                m.manager,
+                tag,
+                m.added.len(),
+                m.removed.len(),
+                m.updated.len(),
+                timing.bright_black()
+            );
+
+            if m.change == ManagerChange::Unchanged {
+                generic!("No changes.");
+            }
+
+            for i in m.removed.iter() {
+                println!("{}", format!("- {}", i).bright_red().bold());
+            }
+
+            for i in m.updated.iter() {
+                println!("{}", format!("~ {}", i).bright_yellow().bold());
+            }
+
+            for i in m.added.iter() {
+                println!("{}", format!("+ {}", i).bright_green().bold());
+            }
+
+            println!();
+        }
+
+        let timing = match self.managers.iter().any(|m| m.duration.is_some()) {
+            true => format!(" in {}", format_duration(self.total_duration())),
+            false => String::new(),
+        };
+
+        info!(
+            "Total: {} added, {} removed, {} updated, across {} managers{}",
+            self.total_added(),
+            self.total_removed(),
+            self.total_updated(),
+            self.managers.len(),
+            timing
+        );
+    }
+}
+
+fn format_duration(duration: Duration) -> String {
+    let secs = duration.as_secs();
+
+    if secs >= 60 {
+        format!("{}m {}s", secs / 60, secs % 60)
+    } else {
+        format!("{:.2}s", duration.as_secs_f64())
+    }
+}
@@ -0,0 +1,59 @@
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+
+use super::Items;
+use crate::facts::{self, Facts};
+use crate::library::*;
+
+// For example:
+//
+// [[when]]
+// os = "fedora"
+// arch = "x86_64"
+// hostname = "*-laptop"
+//
+// [when.managers.system]
+// items = ["tlp"]
+
+/// A `[[when]]` block: imports and items that only apply on hosts matching every condition.
+#[derive(PartialEq, Serialize, Deserialize, JsonSchema, Debug, Default)]
+#[serde(deny_unknown_fields, default)]
+pub struct When {
+    /// Pattern for the hostname. (`*` and `?` work.)
+    pub hostname: Option<String>,
+    /// Pattern for the `ID` in os-release.
+    pub os: Option<String>,
+    /// Pattern for the CPU architecture, as `rebos facts` prints it.
+    pub arch: Option<String>,
+    /// An executable that has to be in `PATH`.
+    pub binary: Option<String>,
+    /// `NAME` if the variable has to be set, or `NAME=pattern` if it has to match.
+    pub env: Option<String>,
+    pub imports: Vec<String>,
+    pub managers: HashMap<String, Items>,
+}
+
+impl When {
+    pub fn matches(&self, facts: &Facts) -> bool {
+        let fact_matches = |pattern: &Option<String>, fact: Option<&String>| match pattern {
+            Some(p) => fact.map(|f| glob_match(p, f)).unwrap_or(false),
+            None => true,
+        };
+
+        fact_matches(&self.hostname, Some(&facts.hostname))
+            && fact_matches(&self.os, facts.os.as_ref())
+            && fact_matches(&self.arch, Some(&facts.arch))
+            && self.binary.as_ref().map(|x| facts::has_binary(x)).unwrap_or(true)
+            && self.env.as_ref().map(|x| env_matches(x)).unwrap_or(true)
+    }
+}
+
+fn env_matches(condition: &str) -> bool {
+    match condition.split_once('=') {
+        Some((name, pattern)) => std::env::var(name)
+            .map(|x| glob_match(pattern, &x))
+            .unwrap_or(false),
+        None => std::env::var_os(condition).is_some(),
+    }
+}
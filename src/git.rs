@@ -1,9 +1,17 @@
-use std::process::Command;
+use gix::bstr::ByteSlice;
 use std::io;
 use piglog::prelude::*;
 use piglog::*;
+use crate::library::custom_error;
 use crate::places;
+use crate::store::{working_files, Files, GenerationStore, UNTRACKED};
 
+/// Who generations are committed as. (The user's own identity and config are never used.)
+const NAME: &str = "Rebos";
+const EMAIL: &str = "rebos@localhost";
+
+/// The state repository. Everything happens in-process, without the user's global or system
+/// Git config, hooks, pager or commit signing getting involved.
 pub struct GitRepo {
     path: String,
 }
@@ -15,27 +23,9 @@ impl GitRepo {
         }
     }
 
-    fn run_git_command(&self, args: &[&str]) -> Result<String, io::Error> {
-        let output = Command::new("git")
-            .args(args)
-            .current_dir(&self.path)
-            .output();
-
-        match output {
-            Ok(output) => {
-                if output.status.success() {
-                    Ok(String::from_utf8_lossy(&output.stdout).trim().to_string())
-                } else {
-                    let stderr = String::from_utf8_lossy(&output.stderr);
-                    error!("Git command failed: {}", stderr);
-                    Err(io::Error::new(io::ErrorKind::Other, stderr.to_string()))
-                }
-            }
-            Err(e) => {
-                error!("Failed to execute git command: {}", e);
-                Err(e)
-            }
-        }
+    fn open(&self) -> Result<gix::Repository, io::Error> {
+        gix::open_opts(&self.path, gix::open::Options::isolated())
+            .map_err(failed("open the state repository"))
     }
 
     pub fn init_if_needed(&self) -> Result<(), io::Error> {
@@ -48,97 +38,251 @@ impl GitRepo {
         
         if !git_dir.exists() {
             info!("Initializing Git repository...");
-            self.run_git_command(&["init"])?;
-            
-            // Configure git user if not set
-            if self.run_git_command(&["config", "user.name"]).is_err() {
-                self.run_git_command(&["config", "user.name", "Rebos"])?;
-            }
-            if self.run_git_command(&["config", "user.email"]).is_err() {
-                self.run_git_command(&["config", "user.email", "rebos@localhost"])?;
-            }
+
+            let repo: gix::Repository = gix::ThreadSafeRepository::init_opts(
+                &self.path,
+                gix::create::Kind::WithWorktree,
+                gix::create::Options::default(),
+                gix::open::Options::isolated(),
+            )
+            .map_err(failed("initialize the state repository"))?
+            .into();
 
             // Create .gitignore
-            let gitignore_path = places::base().join(".gitignore");
-            std::fs::write(&gitignore_path, "lock\n")?;
-            self.run_git_command(&["add", ".gitignore"])?;
-            self.run_git_command(&["commit", "-m", "Initial commit"])?;
+            self.ensure_ignored()?;
+
+            let gitignore = std::fs::read(places::base().join(".gitignore"))?;
+            let tree = write_tree(&repo, &Files::from([(String::from(".gitignore"), gitignore)]))?;
+
+            commit_tree(&repo, "Initial commit", tree, None)?;
             
             success!("Git repository initialized");
         }
 
+        self.ensure_ignored()?;
+
         Ok(())
     }
 
-    pub fn commit(&self, message: &str) -> Result<String, io::Error> {
+    fn ensure_ignored(&self) -> Result<(), io::Error> {
+        let gitignore_path = places::base().join(".gitignore");
+
+        let mut gitignore = match std::fs::read_to_string(&gitignore_path) {
+            Ok(o) => o,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => String::new(),
+            Err(e) => return Err(e),
+        };
+
+        let missing: Vec<&str> = UNTRACKED
+            .into_iter()
+            .filter(|x| !gitignore.lines().any(|line| line.trim() == *x))
+            .collect();
+
+        if missing.is_empty() {
+            return Ok(());
+        }
+
+        if !gitignore.is_empty() && !gitignore.ends_with('\n') {
+            gitignore.push('\n');
+        }
+
+        for i in missing {
+            gitignore.push_str(&format!("{}\n", i));
+        }
+
+        std::fs::write(&gitignore_path, gitignore)
+    }
+
+}
+
+impl GenerationStore for GitRepo {
+    fn commit(&self, message: &str) -> Result<String, io::Error> {
         self.init_if_needed()?;
-        
-        // Add all changes
-        self.run_git_command(&["add", "."])?;
-        
-        // Check if there are changes to commit
-        let status = self.run_git_command(&["status", "--porcelain"])?;
-        if status.trim().is_empty() {
-            warning!("No changes to commit");
-            return Ok(String::new());
+
+        let repo = self.open()?;
+
+        let tree = write_tree(&repo, &working_files(&places::base())?)?;
+
+        let head = head_commit(&repo)?;
+
+        if let Some(ref head) = head {
+            if head.tree_id().map_err(failed("read the latest commit"))? == tree {
+                warning!("No changes to commit");
+                return Ok(String::new());
+            }
         }
 
-        // Commit changes
-        self.run_git_command(&["commit", "-m", message])?;
-        
-        // Get commit hash
-        let hash = self.get_current_hash()?;
+        let hash = commit_tree(&repo, message, tree, head.map(|x| x.id))?;
         success!("Committed generation: {}", hash);
         
         Ok(hash)
     }
 
-    pub fn get_current_hash(&self) -> Result<String, io::Error> {
-        self.run_git_command(&["rev-parse", "HEAD"])
+    fn current_revision(&self) -> Result<String, io::Error> {
+        // Callers report this themselves, a fresh state directory isn't an error here.
+        if !places::base().join(".git").exists() {
+            return Err(io::Error::new(io::ErrorKind::NotFound, "No commits yet"));
+        }
+
+        match head_commit(&self.open()?)? {
+            Some(s) => Ok(s.id.to_string()),
+            None => Err(io::Error::new(io::ErrorKind::NotFound, "No commits yet")),
+        }
     }
 
-    pub fn get_file_content_at_hash(&self, hash: &str, file_path: &str) -> Result<String, io::Error> {
-        self.run_git_command(&["show", &format!("{}:{}", hash, file_path)])
+    fn file_at(&self, hash: &str, file_path: &str) -> Result<String, io::Error> {
+        let repo = self.open()?;
+
+        let commit = find_commit(&repo, hash)?;
+
+        match entry_at(&commit, file_path)? {
+            Some(s) => {
+                let blob = repo.find_object(s).map_err(failed("read a file from the state repository"))?;
+
+                Ok(String::from_utf8_lossy(&blob.data).to_string())
+            }
+            None => Err(io::Error::new(
+                io::ErrorKind::NotFound,
+                format!("'{file_path}' does not exist in {hash}"),
+            )),
+        }
     }
 
-    pub fn log(&self, limit: Option<usize>) -> Result<Vec<(String, String)>, io::Error> {
-        let output = if let Some(limit) = limit {
-            self.run_git_command(&["log", "--pretty=format:%H|%s", &format!("-{}", limit)])?
-        } else {
-            self.run_git_command(&["log", "--pretty=format:%H|%s"])?
-        };
-        
+    fn log(&self, limit: Option<usize>, path: Option<&str>) -> Result<Vec<(String, String)>, io::Error> {
         let mut commits = Vec::new();
-        for line in output.lines() {
-            if let Some((hash, message)) = line.split_once('|') {
-                commits.push((hash.to_string(), message.to_string()));
+
+        // Nothing has been committed before the first commit creates the repository.
+        if !places::base().join(".git").exists() {
+            return Ok(commits);
+        }
+
+        let repo = self.open()?;
+
+        let head = match head_commit(&repo)? {
+            Some(s) => s,
+            None => return Ok(commits),
+        };
+
+        let walk = head
+            .ancestors()
+            .all()
+            .map_err(failed("walk the history of the state repository"))?;
+
+        for info in walk {
+            if limit.is_some_and(|x| commits.len() >= x) {
+                break;
+            }
+
+            let info = info.map_err(failed("walk the history of the state repository"))?;
+            let commit = info.object().map_err(failed("read a commit"))?;
+
+            // Like 'git log -- <path>', only commits that changed the path.
+            if let Some(path) = path {
+                let before = match info.parent_ids().next() {
+                    Some(parent) => entry_at(&parent.object().map_err(failed("read a commit"))?.into_commit(), path)?,
+                    None => None,
+                };
+
+                if entry_at(&commit, path)? == before {
+                    continue;
+                }
             }
+
+            let message = commit.message_raw_sloppy().to_str_lossy().to_string();
+
+            commits.push((info.id.to_string(), message));
         }
 
         Ok(commits)
     }
 
-    pub fn checkout(&self, hash: &str) -> Result<(), io::Error> {
-        // Stash any changes before checkout
-        if self.is_dirty()? {
-            self.run_git_command(&["stash", "push", "-m", "Auto-stash before rollback"])?;
-        }
-        
-        self.run_git_command(&["checkout", hash])?;
-        success!("Checked out generation: {}", hash);
-        Ok(())
+}
+
+/// Report a failed Git operation, and turn its error into an `io::Error`.
+fn failed<E: std::fmt::Display>(what: &str) -> impl FnOnce(E) -> io::Error + '_ {
+    move |e| {
+        error!("Failed to {what}: {e}");
+        custom_error(&format!("Failed to {what}!"))
     }
+}
+
+/// The commit HEAD points to. (`None` in a repository without commits.)
+fn head_commit(repo: &gix::Repository) -> Result<Option<gix::Commit<'_>>, io::Error> {
+    let head = repo.head().map_err(failed("read HEAD"))?;
 
-    pub fn get_diff(&self, from_hash: &str, to_hash: &str) -> Result<String, io::Error> {
-        self.run_git_command(&["diff", &format!("{}..{}", from_hash, to_hash)])
+    if head.is_unborn() {
+        return Ok(None);
     }
 
-    pub fn is_dirty(&self) -> Result<bool, io::Error> {
-        let status = self.run_git_command(&["status", "--porcelain"])?;
-        Ok(!status.trim().is_empty())
+    repo.head_commit().map(Some).map_err(failed("read HEAD"))
+}
+
+fn find_commit<'a>(repo: &'a gix::Repository, hash: &str) -> Result<gix::Commit<'a>, io::Error> {
+    let id = match gix::ObjectId::from_hex(hash.as_bytes()) {
+        Ok(o) => o,
+        Err(_) => {
+            error!("Not a commit hash: {}", hash);
+            return Err(custom_error("Not a commit hash!"));
+        }
+    };
+
+    repo.find_commit(id).map_err(failed("find commit"))
+}
+
+/// The object at a path in a commit.
+fn entry_at(commit: &gix::Commit<'_>, path: &str) -> Result<Option<gix::ObjectId>, io::Error> {
+    let tree = commit.tree().map_err(failed("read a commit"))?;
+
+    let entry = tree.lookup_entry_by_path(path).map_err(failed("read a commit"))?;
+
+    Ok(entry.map(|x| x.object_id()))
+}
+
+fn write_tree(repo: &gix::Repository, files: &Files) -> Result<gix::ObjectId, io::Error> {
+    let mut editor = repo
+        .edit_tree(gix::ObjectId::empty_tree(repo.object_hash()))
+        .map_err(failed("build a tree"))?;
+
+    for (path, content) in files.iter() {
+        let blob = repo.write_blob(content).map_err(failed("write a file to the state repository"))?;
+
+        editor
+            .upsert(path, gix::objs::tree::EntryKind::Blob, blob)
+            .map_err(failed("build a tree"))?;
     }
+
+    Ok(editor.write().map_err(failed("build a tree"))?.detach())
+}
+
+/// Commit a tree on top of HEAD, and make the index match so plain 'git' sees a clean state.
+fn commit_tree(
+    repo: &gix::Repository,
+    message: &str,
+    tree: gix::ObjectId,
+    parent: Option<gix::ObjectId>,
+) -> Result<String, io::Error> {
+    let signature = gix::actor::Signature {
+        name: NAME.into(),
+        email: EMAIL.into(),
+        time: gix::date::Time::now_local_or_utc(),
+    };
+
+    let mut time = gix::date::parse::TimeBuf::default();
+    let signature = signature.to_ref(&mut time);
+
+    let id = repo
+        .commit_as(signature, signature, "HEAD", message, tree, parent)
+        .map_err(failed("commit"))?;
+
+    let mut index = repo.index_from_tree(&tree).map_err(failed("update the index"))?;
+
+    index
+        .write(gix::index::write::Options::default())
+        .map_err(failed("update the index"))?;
+
+    Ok(id.to_string())
 }
 
 pub fn repo() -> GitRepo {
     GitRepo::new()
-}
\ No newline at end of file
+}
@@ -1,9 +1,15 @@
 use std::io;
+use std::path::PathBuf;
 use crate::library;
+use crate::output::log_core_print;
 use piglog::prelude::*;
 
+pub fn path(hook_name: &str) -> PathBuf {
+    crate::places::base_user().join("hooks").join(hook_name)
+}
+
 pub fn run(hook_name: &str) -> Result<(), io::Error> {
-    let hook_path = crate::places::base_user().join("hooks").join(hook_name);
+    let hook_path = path(hook_name);
 
     if hook_path.exists() {
         crate::info!("Running hook: {}", hook_name);
@@ -19,3 +25,12 @@ pub fn run(hook_name: &str) -> Result<(), io::Error> {
 
     Ok(())
 }
+
+/// Like `run()`, but only reports the hook if it would have fired.
+pub fn dry_run(hook_name: &str) {
+    let hook_path = path(hook_name);
+
+    if hook_path.exists() {
+        crate::note!("Would run hook: {} ({})", hook_name, hook_path.display());
+    }
+}
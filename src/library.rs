@@ -5,25 +5,32 @@ use std::collections::{HashMap, HashSet};
 use piglog::prelude::*;
 use piglog::*;
 use std::io;
-use std::process::Command;
+use std::process::{Command, Stdio};
 
 
-use crate::generation::Generation;
+use crate::generation::{Generation, Item};
 
 #[derive(PartialEq)]
 pub enum HistoryMode {
     Remove,
     Add,
+    Update,
 }
 
 pub struct History {
     pub mode: HistoryMode,
-    pub line: String,
+    pub item: Item,
+    /// What the item looked like before. (Only set for updates.)
+    pub previous: Option<Item>,
 }
 
 
 
 pub fn run_command(command: &str) -> bool {
+    if crate::output::is_buffering() {
+        return run_command_buffered(command);
+    }
+
     match Command::new("bash").args(["-c", command]).status() {
         Ok(o) => o,
         Err(_e) => return false,
@@ -31,6 +38,23 @@ pub fn run_command(command: &str) -> bool {
     .success()
 }
 
+// Nobody would see a prompt while the output is held back, so stdin is closed instead.
+fn run_command_buffered(command: &str) -> bool {
+    match Command::new("bash")
+        .args(["-c", command])
+        .stdin(Stdio::null())
+        .output()
+    {
+        Ok(output) => {
+            crate::output::raw(&output.stdout);
+            crate::output::raw(&output.stderr);
+
+            output.status.success()
+        }
+        Err(_e) => false,
+    }
+}
+
 pub fn run_command_with_output(command: &str) -> Option<String> {
     match Command::new("bash").args(["-c", command]).output() {
         Ok(output) => {
@@ -108,6 +132,34 @@ pub fn remove_array_duplicates<T: Clone + PartialEq + Eq + std::hash::Hash>(dup_
     dup_vec.iter().filter(|item| seen.insert(item)).cloned().collect()
 }
 
+/// Shell-style pattern matching, where `*` matches any run of characters and `?` any one.
+pub fn glob_match(pattern: &str, text: &str) -> bool {
+    let pattern: Vec<char> = pattern.chars().collect();
+    let text: Vec<char> = text.chars().collect();
+
+    let (mut p, mut t) = (0, 0);
+    let mut star: Option<(usize, usize)> = None;
+
+    while t < text.len() {
+        if p < pattern.len() && (pattern[p] == '?' || pattern[p] == text[t]) {
+            p += 1;
+            t += 1;
+        } else if p < pattern.len() && pattern[p] == '*' {
+            star = Some((p, t));
+            p += 1;
+        } else if let Some((star_p, star_t)) = star {
+            // Let the last '*' swallow one more character and try again.
+            p = star_p + 1;
+            t = star_t + 1;
+            star = Some((star_p, star_t + 1));
+        } else {
+            return false;
+        }
+    }
+
+    pattern[p..].iter().all(|x| *x == '*')
+}
+
 pub fn history_gen(gen_1: &Generation, gen_2: &Generation) -> HashMap<String, Vec<History>> {
     let mut history_map: HashMap<String, Vec<History>> = HashMap::new();
 
@@ -125,7 +177,8 @@ pub fn history_gen(gen_1: &Generation, gen_2: &Generation) -> HashMap<String, Ve
                     .iter()
                     .map(|x| History {
                         mode: HistoryMode::Add,
-                        line: x.to_string(),
+                        item: x.clone(),
+                        previous: None,
                     })
                     .collect(),
             ),
@@ -145,7 +198,8 @@ pub fn history_gen(gen_1: &Generation, gen_2: &Generation) -> HashMap<String, Ve
                         .iter()
                         .map(|x| History {
                             mode: HistoryMode::Remove,
-                            line: x.to_string(),
+                            item: x.clone(),
+                            previous: None,
                         })
                         .collect(),
                 );
@@ -169,34 +223,60 @@ pub fn print_history_gen(history: &HashMap<String, Vec<History>>) {
 pub fn print_history(diff_vec: &Vec<History>) {
     for i in diff_vec.iter() {
         match i.mode {
-            HistoryMode::Add => println!("{}", format!("+ {}", i.line).bright_green().bold()),
-            HistoryMode::Remove => println!("{}", format!("- {}", i.line).bright_red().bold()),
+            HistoryMode::Add => println!("{}", format!("+ {}", i.item).bright_green().bold()),
+            HistoryMode::Remove => println!("{}", format!("- {}", i.item).bright_red().bold()),
+            HistoryMode::Update => println!("{}", format!("~ {}", i.item).bright_yellow().bold()),
         };
     }
 }
 
-pub fn history(array_1: &[String], array_2: &[String]) -> Vec<History> {
-    let set_1: HashSet<&String> = array_1.iter().collect();
-    let set_2: HashSet<&String> = array_2.iter().collect();
+/// Compare two lists of items by name. Items that exist in both, but with different attributes,
+/// show up as updates.
+pub fn history(array_1: &[Item], array_2: &[Item]) -> Vec<History> {
+    let map_1: HashMap<&String, &Item> = array_1.iter().map(|x| (&x.name, x)).collect();
+    let map_2: HashMap<&String, &Item> = array_2.iter().map(|x| (&x.name, x)).collect();
 
     let mut history_vec: Vec<History> = Vec::new();
 
-    for item in &set_1 {
-        if !item.trim().is_empty() && !set_2.contains(item) {
+    let mut seen_1: HashSet<&String> = HashSet::new();
+    let mut seen_2: HashSet<&String> = HashSet::new();
+
+    // Walk the arrays (not the maps), so the order is the same on every run.
+    for item in array_1.iter() {
+        if item.name.trim().is_empty() || !seen_1.insert(&item.name) {
+            continue;
+        }
+
+        if !map_2.contains_key(&item.name) {
             history_vec.push(History {
                 mode: HistoryMode::Remove,
-                line: (*item).clone(),
+                item: map_1[&item.name].clone(),
+                previous: None,
             });
         }
     }
 
-    for item in &set_2 {
-        if !item.trim().is_empty() && !set_1.contains(item) {
-            history_vec.push(History {
-                mode: HistoryMode::Add,
-                line: (*item).clone(),
-            });
+    for item in array_2.iter() {
+        if item.name.trim().is_empty() || !seen_2.insert(&item.name) {
+            continue;
         }
+
+        // Take the last definition of the item, the same one the map holds.
+        let item = map_2[&item.name];
+
+        match map_1.get(&item.name) {
+            None => history_vec.push(History {
+                mode: HistoryMode::Add,
+                item: item.clone(),
+                previous: None,
+            }),
+            Some(previous) if *previous != item => history_vec.push(History {
+                mode: HistoryMode::Update,
+                item: item.clone(),
+                previous: Some((*previous).clone()),
+            }),
+            Some(_) => (),
+        };
     }
 
     history_vec
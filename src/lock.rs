@@ -0,0 +1,93 @@
+use piglog::prelude::*;
+use piglog::*;
+use std::fs::OpenOptions;
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+
+use crate::library::*;
+use crate::places;
+
+/// Advisory lock on the Rebos state, released when dropped.
+pub struct Lock {
+    path: PathBuf,
+}
+
+impl Drop for Lock {
+    fn drop(&mut self) {
+        if let Err(e) = std::fs::remove_file(&self.path) {
+            warning!("Failed to remove lock file: {} ({e})", self.path.display());
+        }
+    }
+}
+
+pub fn path() -> PathBuf {
+    places::base().join("lock")
+}
+
+/// Take the lock for `command`, or fail if another live Rebos process holds it.
+pub fn acquire(command: &str) -> Result<Lock, io::Error> {
+    let path = path();
+
+    match try_create(&path, command) {
+        Ok(o) => return Ok(o),
+        Err(e) if e.kind() == io::ErrorKind::AlreadyExists => (),
+        Err(e) => {
+            error!("Failed to create lock file: {}", path.display());
+            return Err(e);
+        }
+    };
+
+    let (pid, holder) = read(&path)?;
+
+    // Without a PID the lock may be getting written right now, so play it safe.
+    if pid.map(is_alive).unwrap_or(true) {
+        let pid = pid.map(|x| x.to_string()).unwrap_or(String::from("unknown"));
+
+        error!("Rebos is already running! (pid {pid}: {holder})");
+        note!("If that is wrong, delete the lock file: {}", path.display());
+
+        return Err(custom_error("Rebos is already running!"));
+    }
+
+    warning!(
+        "Removing stale lock left by a process that is gone. (pid {}: {holder})",
+        pid.unwrap_or(0)
+    );
+
+    std::fs::remove_file(&path)?;
+
+    match try_create(&path, command) {
+        Ok(o) => Ok(o),
+        Err(e) => {
+            error!("Failed to create lock file: {}", path.display());
+            Err(e)
+        }
+    }
+}
+
+fn try_create(path: &Path, command: &str) -> Result<Lock, io::Error> {
+    let mut file = OpenOptions::new().write(true).create_new(true).open(path)?;
+
+    let lock = Lock {
+        path: path.to_path_buf(),
+    };
+
+    writeln!(file, "{}", std::process::id())?;
+    writeln!(file, "{}", command)?;
+
+    Ok(lock)
+}
+
+fn read(path: &Path) -> Result<(Option<u32>, String), io::Error> {
+    let lock_string = std::fs::read_to_string(path)?;
+    let mut lines = lock_string.lines();
+
+    let pid: Option<u32> = lines.next().and_then(|x| x.trim().parse().ok());
+    let holder = lines.next().unwrap_or("unknown command").to_string();
+
+    Ok((pid, holder))
+}
+
+fn is_alive(pid: u32) -> bool {
+    Path::new("/proc").join(pid.to_string()).exists()
+}
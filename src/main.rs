@@ -1,15 +1,21 @@
 mod cli;
 mod config;
+mod diagnostic;
 mod dispatch;
+mod facts;
 mod generation;
 mod git;
 mod hook;
 mod library;
+mod lock;
 
 mod management;
 mod obj_print;
 mod obj_print_boilerplate;
+mod output;
 mod places;
+mod store;
+mod vars;
 
 
 
@@ -100,7 +106,7 @@ fn app() -> ExitCode {
     let args = cli::Cli::parse();
 
     match &args.command {
-        cli::Commands::Setup => (),
+        cli::Commands::Setup | cli::Commands::Facts => (),
         _ => {
             if places::base().exists() == false {
                 error!("It seems that the program is not set up!");
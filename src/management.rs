@@ -4,112 +4,234 @@ use colored::Colorize;
 use std::path::{Path, PathBuf};
 use piglog::prelude::*;
 use piglog::*;
-use serde::Deserialize;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap};
 use std::io;
 
 use crate::config::ConfigSide;
-use crate::generation::{gen, Items};
+use crate::diagnostic;
+use crate::facts::Facts;
+use crate::generation::item::ATTRIBUTES;
+use crate::generation::{gen, Item, Items};
 use crate::library::*;
+use crate::output::{log_core_print, log_generic_print};
 use crate::obj_print_boilerplate::macros::print_entry;
+use crate::vars;
 use crate::{bool_question, places};
 
-#[derive(Deserialize, Debug, Default)]
+#[derive(PartialEq, Serialize, Deserialize, Debug, Clone, Copy)]
+#[serde(rename_all = "snake_case")]
+pub enum Operation {
+    Add,
+    Remove,
+    Update,
+}
+
+impl Operation {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::Add => "add",
+            Self::Remove => "remove",
+            Self::Update => "update",
+        }
+    }
+
+    pub fn past_tense(&self) -> &'static str {
+        match self {
+            Self::Add => "added",
+            Self::Remove => "removed",
+            Self::Update => "updated",
+        }
+    }
+
+    /// The operation that undoes this one. (An update is undone by updating to the old item.)
+    pub fn inverse(&self) -> Self {
+        match self {
+            Self::Add => Self::Remove,
+            Self::Remove => Self::Add,
+            Self::Update => Self::Update,
+        }
+    }
+}
+
+#[derive(Deserialize, JsonSchema, Debug, Default)]
 #[serde(deny_unknown_fields, default)]
 pub struct ManagerConfig {
     #[serde(default = "default_many_args")]
     pub many_args: bool,
     #[serde(default = "default_arg_sep")]
     pub arg_sep: String,
+    /// How each item is written into '#:?'.
+    #[serde(default = "default_item_format")]
+    pub item_format: String,
+    /// How an attribute is written into '#:<attribute>' when an item has it. ('#:?' is the value.)
+    pub attribute_formats: HashMap<String, String>,
 }
 
 fn default_many_args() -> bool { true }
 fn default_arg_sep() -> String { String::from(" ") }
+fn default_item_format() -> String { String::from("#:name") }
 
-#[derive(Deserialize, Debug)]
+#[derive(Deserialize, JsonSchema, Debug)]
 #[serde(deny_unknown_fields)]
 pub struct Manager {
     pub add: String,
     pub remove: String,
+    /// Used when only the attributes of an item changed. (Falls back to 'add'.)
+    pub update: Option<String>,
     pub sync: Option<String>,
     pub upgrade: Option<String>,
     pub list: Option<String>,
     pub config: ManagerConfig,
     pub hook_name: String,
     pub plural_name: String,
+    #[serde(default)]
+    pub after: Vec<String>,
+    #[serde(default)]
+    pub before: Vec<String>,
+    /// Managers that must never have an item in common with this one.
+    #[serde(default)]
+    pub conflicts: Vec<String>,
+    /// Variables for `${name}`. (Already substituted by the time the file is deserialized.)
+    #[serde(default)]
+    pub vars: BTreeMap<String, String>,
+    #[serde(skip)]
+    pub dry_run: bool,
 }
 
 impl Manager {
-    fn join_args(&self, items: &[String]) -> String {
-        items.join(&self.config.arg_sep)
+    /// Fill in the item's `#:name` and `#:<attribute>` placeholders.
+    fn expand(&self, template: &str, item: &Item) -> String {
+        let mut expanded = template.replace("#:name", &item.name);
+
+        for a in ATTRIBUTES {
+            let value = match item.attribute(a) {
+                Some(v) => match self.config.attribute_formats.get(a) {
+                    Some(format) => format.replace("#:?", v),
+                    None => v.to_string(),
+                },
+                None => String::new(),
+            };
+
+            expanded = expanded.replace(&format!("#:{a}"), &value);
+        }
+
+        expanded
     }
 
-    pub fn add(&self, items: &[String]) -> Result<(), io::Error> {
-        let many = self.config.many_args;
+    fn join_args(&self, items: &[Item]) -> String {
+        items
+            .iter()
+            .map(|x| self.expand(&self.config.item_format, x))
+            .collect::<Vec<String>>()
+            .join(&self.config.arg_sep)
+    }
 
-        crate::hook::run(&format!("pre_{}_add", self.hook_name))?;
+    /// The command to run for a batch. Per-item placeholders only apply to batches of one item.
+    pub fn command(&self, op: Operation, batch: &[Item]) -> String {
+        let template = match op {
+            Operation::Add => &self.add,
+            Operation::Remove => &self.remove,
+            Operation::Update => self.update.as_ref().unwrap_or(&self.add),
+        };
 
-        if many {
-            self.add_raw(&self.join_args(items))?;
-        } else {
-            for i in items {
-                self.add_raw(i)?;
-            }
+        let command = template.replace("#:?", &self.join_args(batch));
+
+        match batch {
+            [item] => self.expand(&command, item),
+            _ => command,
         }
+    }
 
-        crate::hook::run(&format!("post_{}_add", self.hook_name))?;
+    fn run_hook(&self, hook_name: &str) -> Result<(), io::Error> {
+        if self.dry_run {
+            crate::hook::dry_run(hook_name);
 
-        Ok(())
-    }
+            return Ok(());
+        }
 
-    pub fn remove(&self, items: &[String]) -> Result<(), io::Error> {
-        let many = self.config.many_args;
+        crate::hook::run(hook_name)
+    }
 
-        crate::hook::run(&format!("pre_{}_remove", self.hook_name))?;
+    pub fn add(&self, items: &[Item]) -> Result<(), io::Error> {
+        self.run_operation(Operation::Add, items, |_| false, |_| Ok(()))
+    }
 
-        if many {
-            self.remove_raw(&self.join_args(items))?;
-        } else {
-            for i in items {
-                self.remove_raw(i)?;
-            }
-        }
+    pub fn remove(&self, items: &[Item]) -> Result<(), io::Error> {
+        self.run_operation(Operation::Remove, items, |_| false, |_| Ok(()))
+    }
 
-        crate::hook::run(&format!("post_{}_remove", self.hook_name))?;
+    /// The items each `add`/`remove` command gets, one entry per command that is run.
+    pub fn batches(&self, items: &[Item]) -> Vec<Vec<Item>> {
+        let batches = match self.config.many_args {
+            true => vec![items.to_vec()],
+            false => items.iter().map(|x| vec![x.clone()]).collect(),
+        };
 
-        Ok(())
+        batches
+            .into_iter()
+            .filter(|x| self.join_args(x).trim() != "")
+            .collect()
     }
 
-    fn add_raw(&self, items: &str) -> Result<(), io::Error> {
-        if items.trim() == "" {
+    /// Run an operation batch by batch. Batches for which `skip` returns true are not run,
+    /// and `done` is called after each batch that succeeds. If every batch is skipped, the
+    /// hooks are skipped as well.
+    pub fn run_operation<S, D>(
+        &self,
+        op: Operation,
+        items: &[Item],
+        skip: S,
+        mut done: D,
+    ) -> Result<(), io::Error>
+    where
+        S: Fn(usize) -> bool,
+        D: FnMut(usize) -> Result<(), io::Error>,
+    {
+        let batches = self.batches(items);
+
+        if !batches.is_empty() && (0..batches.len()).all(&skip) {
             return Ok(());
         }
 
-        match run_command(self.add.as_str().replace("#:?", items).as_str()) {
-            true => info!("Successfully added {}!", self.plural_name),
-            false => {
-                error!("Failed to add {}!", self.plural_name);
+        self.run_hook(&format!("pre_{}_{}", self.hook_name, op.name()))?;
 
-                return Err(custom_error(
-                    format!("Failed to add {}!", self.plural_name).as_str(),
-                ));
+        for (i, batch) in batches.iter().enumerate() {
+            if skip(i) {
+                continue;
             }
-        };
+
+            self.run_raw(op, batch)?;
+
+            done(i)?;
+        }
+
+        self.run_hook(&format!("post_{}_{}", self.hook_name, op.name()))?;
 
         Ok(())
     }
 
-    fn remove_raw(&self, items: &str) -> Result<(), io::Error> {
-        if items.trim() == "" {
+    fn run_raw(&self, op: Operation, batch: &[Item]) -> Result<(), io::Error> {
+        if self.join_args(batch).trim() == "" {
             return Ok(());
         }
 
-        match run_command(self.remove.as_str().replace("#:?", items).as_str()) {
-            true => info!("Successfully removed {}!", self.plural_name),
+        let command = self.command(op, batch);
+
+        if self.dry_run {
+            generic!("{}", command);
+
+            return Ok(());
+        }
+
+        match run_command(&command) {
+            true => info!("Successfully {} {}!", op.past_tense(), self.plural_name),
             false => {
-                error!("Failed to remove {}!", self.plural_name);
+                error!("Failed to {} {}!", op.name(), self.plural_name);
 
                 return Err(custom_error(
-                    format!("Failed to remove {}!", self.plural_name).as_str(),
+                    format!("Failed to {} {}!", op.name(), self.plural_name).as_str(),
                 ));
             }
         };
@@ -118,7 +240,7 @@ impl Manager {
     }
 
     pub fn sync(&self) -> Result<(), io::Error> {
-        crate::hook::run(&format!("pre_{}_sync", self.hook_name))?;
+        self.run_hook(&format!("pre_{}_sync", self.hook_name))?;
 
         if let Some(ref s) = self.sync {
             match run_command(s) {
@@ -131,13 +253,13 @@ impl Manager {
             };
         }
 
-        crate::hook::run(&format!("post_{}_sync", self.hook_name))?;
+        self.run_hook(&format!("post_{}_sync", self.hook_name))?;
 
         Ok(())
     }
 
     pub fn upgrade(&self) -> Result<(), io::Error> {
-        crate::hook::run(&format!("pre_{}_upgrade", self.hook_name))?;
+        self.run_hook(&format!("pre_{}_upgrade", self.hook_name))?;
 
         if let Some(ref s) = self.upgrade {
             match run_command(s) {
@@ -152,15 +274,15 @@ impl Manager {
             };
         }
 
-        crate::hook::run(&format!("post_{}_upgrade", self.hook_name))?;
+        self.run_hook(&format!("post_{}_upgrade", self.hook_name))?;
 
         Ok(())
     }
 
-    pub fn get_other(&self, items: &[String]) -> Result<Vec<String>, io::Error> {
+    pub fn get_other(&self, items: &[Item]) -> Result<Vec<String>, io::Error> {
         if self.list.is_some() {
             let mut others = self.list()?;
-            others.retain(|other| !items.contains(other));
+            others.retain(|other| !items.iter().any(|x| x.name == *other));
             Ok(others)
         } else {
             Ok(Vec::new())
@@ -186,6 +308,10 @@ impl Manager {
         self.plural_name = pn.to_string();
     }
 
+    pub fn set_dry_run(&mut self, dry_run: bool) {
+        self.dry_run = dry_run;
+    }
+
     pub fn check_config(&self) -> Result<(), Vec<String>> {
         let mut errors: Vec<String> = Vec::new();
 
@@ -203,6 +329,21 @@ impl Manager {
             ));
         }
 
+        if self.config.many_args {
+            let per_item: Vec<String> = std::iter::once("name")
+                .chain(ATTRIBUTES)
+                .map(|x| format!("#:{x}"))
+                .collect();
+
+            for (field, command) in [("add", Some(&self.add)), ("remove", Some(&self.remove)), ("update", self.update.as_ref())] {
+                if command.map(|c| per_item.iter().any(|p| c.contains(p))).unwrap_or(false) {
+                    errors.push(format!(
+                        "Field '{field}' uses per-item placeholders, which need 'many_args = false'! (Use 'item_format' instead.)"
+                    ));
+                }
+            }
+        }
+
         if errors.len() > 0 {
             return Err(errors);
         }
@@ -227,11 +368,12 @@ pub fn load_manager_no_config_check(man: &str) -> Result<Manager, io::Error> {
         }
     };
 
+    let man_string = vars::interpolate(&path, &man_string, &vars::global(&Facts::detect()?)?)?;
+
     let manager: Manager = match toml::from_str(&man_string) {
         Ok(o) => o,
         Err(e) => {
-            piglog::fatal!("Failed to deserialize manager! ({man})");
-            piglog::fatal!("Error: {e:#?}");
+            diagnostic::toml_error(&path, &man_string, &e);
 
             return Err(io::Error::new(
                 io::ErrorKind::Other,
@@ -352,7 +494,7 @@ fn list_others_core(man_name: &String, items: &Items, remove: bool) -> Result<()
         print_entry!(man_name, others);
 
         if remove && bool_question("Remove items?", false) {
-            man.remove(&others)?;
+            man.remove(&others.iter().map(|x| Item::new(x)).collect::<Vec<Item>>())?;
         }
     };
     Ok(())
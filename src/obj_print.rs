@@ -1,7 +1,10 @@
 #![allow(dead_code)]
 
+use colored::Colorize;
 use piglog::prelude::*;
+use piglog::*;
 
+use crate::generation::provenance::Provenance;
 use crate::generation::Generation;
 use crate::obj_print_boilerplate::macros::*;
 
@@ -11,4 +14,75 @@ pub fn generation(gen: &Generation) {
     for i in gen.managers.keys() {
         print_entry!(i, gen.managers.get(i).unwrap().items);
     }
+
+    exclusions(gen);
+}
+
+/// Like `generation()`, but every item is followed by the files that declare it.
+pub fn generation_sources(gen: &Generation) {
+    println!();
+
+    for i in gen.managers.keys() {
+        info!("{}:", i);
+
+        for item in gen.managers.get(i).unwrap().items.iter() {
+            let files: Vec<&str> = gen
+                .provenance
+                .iter()
+                .filter(|x| x.manager == *i && x.item == item.name)
+                .map(|x| x.file())
+                .collect();
+
+            generic!("{} {}", item, format!("({})", files.join(", ")).bright_black());
+        }
+
+        println!();
+    }
+
+    exclusions(gen);
+}
+
+/// Print where an item was declared, and which imports pulled it in. (False if it is nowhere.)
+pub fn why(gen: &Generation, item: &str) -> bool {
+    let declared: Vec<&Provenance> = gen.provenance.iter().filter(|x| x.item == item).collect();
+
+    if declared.is_empty() {
+        return false;
+    }
+
+    for p in declared.iter() {
+        info!("{} ({}): declared in {}", item, p.manager, p.file());
+
+        if p.chain.len() > 1 {
+            generic!("Imported via: {}", p.chain.join(" -> "));
+        }
+    }
+
+    for e in gen.exclusions.iter() {
+        if e.excluded.iter().any(|x| x.name == item) {
+            warning!("{} ({}): excluded by '{}' in {}", item, e.manager, e.pattern, e.source);
+        }
+    }
+
+    true
+}
+
+fn exclusions(gen: &Generation) {
+    if gen.exclusions.is_empty() {
+        return;
+    }
+
+    info!("Excluded:");
+
+    for e in gen.exclusions.iter() {
+        if e.excluded.is_empty() {
+            generic!("{}: '{}' matched nothing (from {})", e.manager, e.pattern, e.source);
+        }
+
+        for i in e.excluded.iter() {
+            generic!("{}: {} (by '{}' from {})", e.manager, i, e.pattern, e.source);
+        }
+    }
+
+    println!();
 }
@@ -0,0 +1,74 @@
+// The piglog macros call whatever `log_core_print`/`log_generic_print` is in scope, so modules
+// that import these two functions can have their messages (and the output of
+// `library::run_command`) held back per thread with `capture()`.
+
+use colored::Colorize;
+use piglog::LogMode;
+use std::cell::RefCell;
+
+pub enum Line {
+    Core(String, LogMode),
+    Generic(String),
+    Raw(String),
+}
+
+thread_local! {
+    static BUFFER: RefCell<Option<Vec<Line>>> = const { RefCell::new(None) };
+}
+
+pub fn is_buffering() -> bool {
+    BUFFER.with(|b| b.borrow().is_some())
+}
+
+/// Run `f`, holding back everything it logs on this thread instead of printing it.
+pub fn capture<T, F: FnOnce() -> T>(f: F) -> (T, Vec<Line>) {
+    BUFFER.with(|b| *b.borrow_mut() = Some(Vec::new()));
+
+    let result = f();
+
+    let lines = BUFFER.with(|b| b.borrow_mut().take()).unwrap_or_default();
+
+    (result, lines)
+}
+
+/// Print lines held back by `capture()`, each one prefixed with `name`.
+pub fn replay(name: &str, lines: Vec<Line>) {
+    let prefix = format!("[{}]", name).bright_cyan().bold();
+
+    for line in lines.into_iter() {
+        match line {
+            Line::Core(msg, mode) => piglog::log_core_print(format!("{} {}", prefix, msg), mode),
+            Line::Generic(msg) => piglog::log_generic_print(format!("{} {}", prefix, msg)),
+            Line::Raw(msg) => println!("{} {}", prefix, msg),
+        };
+    }
+}
+
+fn push(line: Line) -> Option<Line> {
+    BUFFER.with(|b| match b.borrow_mut().as_mut() {
+        Some(buffer) => {
+            buffer.push(line);
+            None
+        }
+        None => Some(line),
+    })
+}
+
+pub fn log_core_print(msg: String, mode: LogMode) {
+    if let Some(Line::Core(msg, mode)) = push(Line::Core(msg, mode)) {
+        piglog::log_core_print(msg, mode);
+    }
+}
+
+pub fn log_generic_print(msg: String) {
+    if let Some(Line::Generic(msg)) = push(Line::Generic(msg)) {
+        piglog::log_generic_print(msg);
+    }
+}
+
+/// Hold back raw command output. (Only call this while buffering.)
+pub fn raw(output: &[u8]) {
+    for line in String::from_utf8_lossy(output).lines() {
+        push(Line::Raw(line.to_string()));
+    }
+}
@@ -3,6 +3,7 @@
 use std::io;
 use std::path::{Path, PathBuf};
 use std::env;
+use std::sync::OnceLock;
 use piglog::prelude::*;
 use piglog::*;
 
@@ -34,8 +35,28 @@ pub fn base() -> PathBuf {
         })
 }
 
+static PROFILE: OnceLock<String> = OnceLock::new();
+
+/// Use the named profile for the rest of this run. (Only the first call has an effect.)
+pub fn set_profile(name: &str) {
+    let _ = PROFILE.set(name.to_string());
+}
+
+pub fn profile() -> Option<&'static str> {
+    PROFILE.get().map(|x| x.as_str())
+}
+
+/// Where the generation state lives. Every profile keeps its own.
 pub fn gens() -> PathBuf {
-    base().join("generations")
+    match profile() {
+        Some(p) => base().join("profiles").join(p),
+        None => base().join("generations"),
+    }
+}
+
+/// The root generation file of a profile.
+pub fn profile_gen(name: &str) -> PathBuf {
+    base_user().join("profiles").join(format!("{name}.toml"))
 }
 
 pub fn base_user() -> PathBuf {
@@ -46,3 +67,15 @@ pub fn base_user() -> PathBuf {
             PathBuf::from(home).join(".config").join("rebos")
         })
 }
+
+/// A path as the user would write it, relative to the user config if it is in there.
+pub fn relative_to_user(path: &Path) -> String {
+    let base = base_user();
+    let base_canonical = std::fs::canonicalize(&base).unwrap_or(base.clone());
+
+    path.strip_prefix(&base)
+        .or_else(|_| path.strip_prefix(&base_canonical))
+        .unwrap_or(path)
+        .display()
+        .to_string()
+}
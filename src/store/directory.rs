@@ -0,0 +1,190 @@
+use piglog::prelude::*;
+use piglog::*;
+use std::io;
+use std::path::PathBuf;
+
+use super::{read_files, working_files, Files, GenerationStore};
+use crate::places;
+
+/// Generations as numbered copies of the state directory, for systems without Git.
+///
+/// Every commit is a folder in `snapshots/` holding the commit message and a copy of every
+/// tracked file. Revisions are the snapshot numbers, counting up from 1.
+pub struct DirectoryStore {
+    base: PathBuf,
+}
+
+impl DirectoryStore {
+    pub fn new() -> Self {
+        Self {
+            base: places::base(),
+        }
+    }
+
+    fn snapshots(&self) -> PathBuf {
+        self.base.join("snapshots")
+    }
+
+    fn head(&self) -> PathBuf {
+        self.snapshots().join("HEAD")
+    }
+
+    /// Every snapshot number, oldest first.
+    fn revisions(&self) -> Result<Vec<usize>, io::Error> {
+        let entries = match std::fs::read_dir(self.snapshots()) {
+            Ok(o) => o,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(Vec::new()),
+            Err(e) => {
+                error!("Failed to read snapshots directory!");
+                return Err(e);
+            }
+        };
+
+        let mut revisions: Vec<usize> = Vec::new();
+
+        for entry in entries {
+            if let Ok(n) = entry?.file_name().to_string_lossy().parse() {
+                revisions.push(n);
+            }
+        }
+
+        revisions.sort();
+
+        Ok(revisions)
+    }
+
+    /// The number of an existing snapshot.
+    fn number(&self, revision: &str) -> Result<usize, io::Error> {
+        match revision.parse::<usize>() {
+            Ok(n) if self.snapshots().join(n.to_string()).is_dir() => Ok(n),
+            _ => Err(io::Error::new(io::ErrorKind::NotFound, format!("No such snapshot: {revision}"))),
+        }
+    }
+
+    fn snapshot_tree(&self, revision: usize) -> Result<Files, io::Error> {
+        let root = self.snapshots().join(revision.to_string()).join("tree");
+
+        match root.exists() {
+            true => read_files(&root),
+            false => Ok(Files::new()),
+        }
+    }
+
+    fn set_head(&self, revision: &str) -> Result<(), io::Error> {
+        match std::fs::write(self.head(), revision) {
+            Ok(_) => Ok(()),
+            Err(e) => {
+                error!("Failed to write snapshot HEAD!");
+                Err(e)
+            }
+        }
+    }
+}
+
+impl GenerationStore for DirectoryStore {
+    fn commit(&self, message: &str) -> Result<String, io::Error> {
+        if !self.base.exists() {
+            error!("Rebos base directory does not exist!");
+            return Err(io::Error::new(io::ErrorKind::NotFound, "Base directory not found"));
+        }
+
+        let tree = working_files(&self.base)?;
+
+        let latest = self.revisions()?.last().copied();
+
+        if let Some(l) = latest {
+            if self.snapshot_tree(l)? == tree {
+                warning!("No changes to commit");
+                return Ok(String::new());
+            }
+        }
+
+        let revision = latest.unwrap_or(0) + 1;
+        let snapshot = self.snapshots().join(revision.to_string());
+
+        // Written to a temporary folder first, so a failed commit never leaves half a snapshot.
+        let staging = self.snapshots().join(format!(".{revision}"));
+
+        if staging.exists() {
+            std::fs::remove_dir_all(&staging)?;
+        }
+
+        for (path, content) in tree.iter() {
+            let target = staging.join("tree").join(path);
+
+            if let Some(parent) = target.parent() {
+                std::fs::create_dir_all(parent)?;
+            }
+
+            std::fs::write(&target, content)?;
+        }
+
+        std::fs::create_dir_all(&staging)?;
+        std::fs::write(staging.join("message"), message)?;
+
+        match std::fs::rename(&staging, &snapshot) {
+            Ok(_) => (),
+            Err(e) => {
+                error!("Failed to save snapshot {}!", revision);
+                return Err(e);
+            }
+        };
+
+        self.set_head(&revision.to_string())?;
+
+        success!("Committed generation: {}", revision);
+
+        Ok(revision.to_string())
+    }
+
+    fn log(&self, limit: Option<usize>, path: Option<&str>) -> Result<Vec<(String, String)>, io::Error> {
+        let mut commits = Vec::new();
+
+        let revisions = self.revisions()?;
+
+        for (i, revision) in revisions.iter().enumerate().rev() {
+            if limit.is_some_and(|x| commits.len() >= x) {
+                break;
+            }
+
+            if let Some(path) = path {
+                let previous = match i {
+                    0 => None,
+                    _ => Some(revisions[i - 1].to_string()),
+                };
+
+                let now = self.file_at(&revision.to_string(), path).ok();
+                let before = previous.and_then(|x| self.file_at(&x, path).ok());
+
+                if now == before {
+                    continue;
+                }
+            }
+
+            let message = std::fs::read_to_string(
+                self.snapshots().join(revision.to_string()).join("message"),
+            )
+            .unwrap_or_default();
+
+            commits.push((revision.to_string(), message));
+        }
+
+        Ok(commits)
+    }
+
+    fn file_at(&self, revision: &str, path: &str) -> Result<String, io::Error> {
+        let n = self.number(revision)?;
+
+        std::fs::read_to_string(self.snapshots().join(n.to_string()).join("tree").join(path))
+    }
+
+    fn current_revision(&self) -> Result<String, io::Error> {
+        match std::fs::read_to_string(self.head()) {
+            Ok(o) => Ok(o.trim().to_string()),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => {
+                Err(io::Error::new(io::ErrorKind::NotFound, "Nothing has been committed yet"))
+            }
+            Err(e) => Err(e),
+        }
+    }
+}
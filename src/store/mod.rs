@@ -0,0 +1,145 @@
+pub mod directory;
+
+use piglog::prelude::*;
+use piglog::*;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::io;
+use std::path::Path;
+
+use crate::diagnostic;
+use crate::git;
+use crate::library::*;
+use crate::places;
+
+/// State files that are never part of a generation. (Which generation is current or built
+/// changes without anything being committed.)
+pub const UNTRACKED: [&str; 7] = [
+    "lock",
+    "generations/journal.toml",
+    "generations/current",
+    "generations/built",
+    "profiles/*/journal.toml",
+    "profiles/*/current",
+    "profiles/*/built",
+];
+
+/// File contents by path, relative to the directory they were read from.
+pub type Files = BTreeMap<String, Vec<u8>>;
+
+/// Where committed generations are kept.
+///
+/// Paths are relative to the state directory, revisions are whatever the store uses to name
+/// a commit (a Git hash, a snapshot number, ...).
+pub trait GenerationStore {
+    /// Record the state directory as a new revision. (Empty if nothing changed.)
+    fn commit(&self, message: &str) -> Result<String, io::Error>;
+
+    /// Revisions and their full messages, newest first. With a `path`, only the ones that changed it.
+    fn log(&self, limit: Option<usize>, path: Option<&str>) -> Result<Vec<(String, String)>, io::Error>;
+
+    /// The contents of a file at a revision. (`NotFound` if it didn't exist there.)
+    fn file_at(&self, revision: &str, path: &str) -> Result<String, io::Error>;
+
+    /// The latest revision.
+    fn current_revision(&self) -> Result<String, io::Error>;
+}
+
+#[derive(PartialEq, Serialize, Deserialize, JsonSchema, Debug, Default, Clone, Copy)]
+#[serde(rename_all = "snake_case")]
+pub enum StoreKind {
+    /// A Git repository.
+    #[default]
+    Git,
+    /// Numbered snapshot folders, readable without any Git tooling.
+    Directory,
+}
+
+/// `rebos.toml`, settings for Rebos itself rather than for the system.
+#[derive(PartialEq, Serialize, Deserialize, JsonSchema, Debug, Default)]
+#[serde(deny_unknown_fields, default)]
+pub struct Settings {
+    /// How generations are stored. Pick this before the first commit, switching later starts
+    /// an empty history.
+    pub store: StoreKind,
+}
+
+pub fn settings() -> Result<Settings, io::Error> {
+    let path = places::base_user().join("rebos.toml");
+
+    let settings_string = match std::fs::read_to_string(&path) {
+        Ok(o) => o,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(Settings::default()),
+        Err(e) => {
+            error!("Failed to read settings file! ({})", path.display());
+            return Err(e);
+        }
+    };
+
+    match toml::from_str(&settings_string) {
+        Ok(o) => Ok(o),
+        Err(e) => {
+            diagnostic::toml_error(&path, &settings_string, &e);
+
+            Err(custom_error("Failed to deserialize rebos.toml!"))
+        }
+    }
+}
+
+/// The store chosen in `rebos.toml`.
+pub fn store() -> Result<Box<dyn GenerationStore>, io::Error> {
+    Ok(match settings()?.store {
+        StoreKind::Git => Box::new(git::repo()),
+        StoreKind::Directory => Box::new(directory::DirectoryStore::new()),
+    })
+}
+
+/// Whether a file in the state directory belongs in a generation.
+pub fn is_tracked(path: &str) -> bool {
+    !UNTRACKED.iter().any(|x| glob_match(x, path))
+}
+
+/// The tracked files of the state directory as they are now. (Without the data of the stores.)
+pub fn working_files(base: &Path) -> Result<Files, io::Error> {
+    let mut files = Files::new();
+
+    read_into(base, base, &mut files, true)?;
+
+    Ok(files)
+}
+
+/// Every file under a directory.
+pub fn read_files(root: &Path) -> Result<Files, io::Error> {
+    let mut files = Files::new();
+
+    read_into(root, root, &mut files, false)?;
+
+    Ok(files)
+}
+
+fn read_into(root: &Path, dir: &Path, files: &mut Files, state: bool) -> Result<(), io::Error> {
+    for entry in std::fs::read_dir(dir)? {
+        let path = entry?.path();
+
+        let relative = path
+            .strip_prefix(root)
+            .unwrap_or(&path)
+            .components()
+            .map(|x| x.as_os_str().to_string_lossy().to_string())
+            .collect::<Vec<String>>()
+            .join("/");
+
+        if state && (relative == "snapshots" || relative == ".git" || !is_tracked(&relative)) {
+            continue;
+        }
+
+        if path.is_dir() {
+            read_into(root, &path, files, state)?;
+        } else {
+            files.insert(relative, std::fs::read(&path)?);
+        }
+    }
+
+    Ok(())
+}
@@ -0,0 +1,334 @@
+use piglog::prelude::*;
+use piglog::*;
+use std::collections::BTreeMap;
+use std::io;
+use std::path::Path;
+
+use crate::config::{config_for, Config, ConfigSide};
+use crate::facts::Facts;
+use crate::library::*;
+use crate::places;
+
+pub type Vars = BTreeMap<String, String>;
+
+/// Variables every file can use without defining them.
+pub fn builtins(facts: &Facts) -> Vars {
+    let mut vars = Vars::new();
+
+    vars.insert(String::from("hostname"), facts.hostname.to_string());
+    vars.insert(String::from("user"), username());
+    vars.insert(String::from("arch"), facts.arch.to_string());
+
+    if let Some(os) = &facts.os {
+        vars.insert(String::from("distro"), os.to_string());
+    }
+
+    vars
+}
+
+/// The built-ins, then the `[vars]` of the root generation file, then the ones of the machine
+/// file. (Later wins.)
+pub fn global(facts: &Facts) -> Result<Vars, io::Error> {
+    let mut vars = builtins(facts);
+
+    for path in [
+        config_for(Config::Generation, ConfigSide::User)?,
+        places::base_user()
+            .join("machines")
+            .join(&facts.hostname)
+            .join("gen.toml"),
+    ] {
+        match std::fs::read_to_string(&path) {
+            Ok(o) => vars.extend(file_vars(&o)),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => (),
+            Err(e) => {
+                error!("Failed to read file for variables! ({})", path.display());
+                return Err(e);
+            }
+        };
+    }
+
+    Ok(vars)
+}
+
+/// Replace every `${name}` inside the strings of a TOML file. The file's own `[vars]` are used
+/// for anything the global ones don't define, and `$${` is written out as a literal `${`.
+/// Comments and everything outside of strings are left alone.
+pub fn interpolate(path: &Path, text: &str, global: &Vars) -> Result<String, io::Error> {
+    let mut vars = file_vars(text);
+    vars.extend(global.clone());
+
+    let mut undefined: Vec<(usize, String)> = Vec::new();
+
+    let mut quote = Quote::None;
+
+    let lines: Vec<String> = text
+        .lines()
+        .enumerate()
+        .map(|(i, line)| {
+            interpolate_line(line, &mut quote, &vars, |name| undefined.push((i + 1, name)))
+        })
+        .collect();
+
+    if !undefined.is_empty() {
+        for (line, name) in undefined.iter() {
+            error!("Undefined variable '${{{name}}}' in {}:{line}", path.display());
+        }
+
+        note!("Define it under [vars], or write '$${{' for a literal '${{'.");
+
+        return Err(custom_error("Undefined variables!"));
+    }
+
+    let mut interpolated = lines.join("\n");
+
+    if text.ends_with('\n') {
+        interpolated.push('\n');
+    }
+
+    Ok(interpolated)
+}
+
+/// The kind of TOML string a line starts or ends in.
+#[derive(PartialEq, Debug, Clone, Copy)]
+enum Quote {
+    None,
+    Basic,
+    Literal,
+    MultiBasic,
+    MultiLiteral,
+}
+
+impl Quote {
+    fn delimiter(&self) -> &'static str {
+        match self {
+            Self::None => "",
+            Self::Basic => "\"",
+            Self::Literal => "'",
+            Self::MultiBasic => "\"\"\"",
+            Self::MultiLiteral => "'''",
+        }
+    }
+
+    fn is_basic(&self) -> bool {
+        matches!(self, Self::Basic | Self::MultiBasic)
+    }
+}
+
+/// Interpolate one line. `quote` is the string the line starts in, and is left at the one the
+/// next line starts in.
+fn interpolate_line<F: FnMut(String)>(
+    line: &str,
+    quote: &mut Quote,
+    vars: &Vars,
+    mut undefined: F,
+) -> String {
+    let mut result = String::new();
+    let mut rest = line;
+
+    while let Some(c) = rest.chars().next() {
+        if *quote == Quote::None {
+            if c == '#' {
+                result.push_str(rest);
+                rest = "";
+
+                break;
+            }
+
+            *quote = if rest.starts_with("\"\"\"") {
+                Quote::MultiBasic
+            } else if rest.starts_with("'''") {
+                Quote::MultiLiteral
+            } else if c == '"' {
+                Quote::Basic
+            } else if c == '\'' {
+                Quote::Literal
+            } else {
+                Quote::None
+            };
+
+            let taken = match *quote {
+                Quote::None => c.len_utf8(),
+                _ => quote.delimiter().len(),
+            };
+
+            result.push_str(&rest[..taken]);
+            rest = &rest[taken..];
+
+            continue;
+        }
+
+        if rest.starts_with(quote.delimiter()) {
+            result.push_str(quote.delimiter());
+            rest = &rest[quote.delimiter().len()..];
+            *quote = Quote::None;
+
+            continue;
+        }
+
+        // Escapes are kept as they are, so an escaped quote doesn't end the string.
+        if quote.is_basic() && c == '\\' {
+            let taken = 1 + rest[1..].chars().next().map(|x| x.len_utf8()).unwrap_or(0);
+
+            result.push_str(&rest[..taken]);
+            rest = &rest[taken..];
+
+            continue;
+        }
+
+        if rest.starts_with("$${") {
+            result.push_str("${");
+            rest = &rest[3..];
+
+            continue;
+        }
+
+        if let Some(name) = variable(rest) {
+            match vars.get(name) {
+                Some(s) if quote.is_basic() => result.push_str(&escape(s)),
+                Some(s) => result.push_str(s),
+                None => {
+                    undefined(name.to_string());
+                    result.push_str(&rest[..name.len() + 3]);
+                }
+            };
+
+            rest = &rest[name.len() + 3..];
+
+            continue;
+        }
+
+        result.push(c);
+        rest = &rest[c.len_utf8()..];
+    }
+
+    result.push_str(rest);
+
+    // Only multi-line strings go on past the end of a line.
+    if matches!(quote, Quote::Basic | Quote::Literal) {
+        *quote = Quote::None;
+    }
+
+    result
+}
+
+/// The name of the `${name}` at the start of `text`. (Not a variable if it is never closed, or
+/// the name isn't one.)
+fn variable(text: &str) -> Option<&str> {
+    let rest = text.strip_prefix("${")?;
+    let name = &rest[..rest.find('}')?];
+
+    match !name.is_empty() && name.chars().all(|x| x.is_alphanumeric() || "_-.".contains(x)) {
+        true => Some(name),
+        false => None,
+    }
+}
+
+/// A value written into a basic string.
+fn escape(value: &str) -> String {
+    value
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n")
+        .replace('\r', "\\r")
+        .replace('\t', "\\t")
+}
+
+/// The `[vars]` table of a file. (Empty if the file can't be parsed, reading it will report that.)
+fn file_vars(text: &str) -> Vars {
+    let table: toml::Table = match toml::from_str(text) {
+        Ok(o) => o,
+        Err(_) => return Vars::new(),
+    };
+
+    match table.get("vars").and_then(|x| x.as_table()) {
+        Some(vars) => vars
+            .iter()
+            .filter_map(|(k, v)| v.as_str().map(|v| (k.to_string(), v.to_string())))
+            .collect(),
+        None => Vars::new(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn vars() -> Vars {
+        Vars::from([
+            (String::from("editor"), String::from("neovim")),
+            (String::from("quoted"), String::from(r#"say "hi" \o/"#)),
+        ])
+    }
+
+    /// Interpolate a single line, with the names of undefined variables.
+    fn line(text: &str) -> (String, Vec<String>) {
+        let mut undefined = Vec::new();
+        let mut quote = Quote::None;
+
+        let result = interpolate_line(text, &mut quote, &vars(), |x| undefined.push(x));
+
+        (result, undefined)
+    }
+
+    #[test]
+    fn substitutes_in_strings() {
+        assert_eq!(line(r#"items = ["${editor}"]"#).0, r#"items = ["neovim"]"#);
+        assert_eq!(line("items = ['${editor}']").0, "items = ['neovim']");
+    }
+
+    #[test]
+    fn leaves_comments_alone() {
+        let comment = "# Variables usable as ${name} (built in: ${distro})";
+
+        assert_eq!(line(comment), (comment.to_string(), Vec::new()));
+        assert_eq!(line(r#"a = "${editor}" # ${name}"#).0, r#"a = "neovim" # ${name}"#);
+        assert_eq!(line(r##"a = "#${editor}""##).0, r##"a = "#neovim""##);
+    }
+
+    #[test]
+    fn escapes() {
+        assert_eq!(line(r#"a = "$${editor}""#).0, r#"a = "${editor}""#);
+        assert_eq!(line(r#"a = "\"${editor}""#).0, r#"a = "\"neovim""#);
+    }
+
+    #[test]
+    fn quotes_values() {
+        let result = line(r#"a = "${quoted}""#).0;
+
+        assert_eq!(result, r#"a = "say \"hi\" \\o/""#);
+
+        let table: toml::Table = toml::from_str(&result).unwrap();
+        assert_eq!(table["a"].as_str(), Some(r#"say "hi" \o/"#));
+    }
+
+    #[test]
+    fn unclosed() {
+        assert_eq!(line(r#"a = "${editor""#), (String::from(r#"a = "${editor""#), Vec::new()));
+        assert_eq!(line(r#"a = "${}""#).1, Vec::<String>::new());
+    }
+
+    #[test]
+    fn undefined() {
+        let (result, undefined) = line(r#"a = ["${nope}", "${editor}", "${other}"]"#);
+
+        assert_eq!(result, r#"a = ["${nope}", "neovim", "${other}"]"#);
+        assert_eq!(undefined, vec![String::from("nope"), String::from("other")]);
+    }
+
+    #[test]
+    fn multi_line_strings() {
+        let mut quote = Quote::None;
+        let mut undefined = Vec::new();
+
+        let first = interpolate_line(r#"a = """${editor}"#, &mut quote, &vars(), |x| undefined.push(x));
+        assert_eq!(quote, Quote::MultiBasic);
+
+        let second = interpolate_line(r#"${editor}""" # ${name}"#, &mut quote, &vars(), |x| undefined.push(x));
+        assert_eq!(quote, Quote::None);
+
+        assert_eq!(first, r#"a = """neovim"#);
+        assert_eq!(second, r#"neovim""" # ${name}"#);
+        assert!(undefined.is_empty());
+    }
+}